@@ -0,0 +1,27 @@
+use minesweeper_rust::preset::Settings;
+use minesweeper_rust::tui::Tui;
+use minesweeper_rust::Game;
+
+fn main() {
+    let mut game = Settings::from_args(std::env::args().skip(1))
+        .and_then(|settings| settings.new_game().map_err(|err| err.to_string()))
+        .unwrap_or_else(|err| {
+            println!("{}", err);
+            std::process::exit(1);
+        });
+
+    if let Err(err) = run(&mut game) {
+        println!("Terminal error! {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn run(game: &mut Game) -> std::io::Result<()> {
+    let mut tui = Tui::new(game)?;
+    loop {
+        tui.draw(game)?;
+        if tui.handle_events(game)? {
+            return Ok(());
+        }
+    }
+}
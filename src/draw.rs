@@ -1,46 +1,264 @@
 use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::mouse::MouseButton;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
-use sdl2::surface::Surface;
+use sdl2::surface::{Surface, SurfaceRef};
+use std::path::PathBuf;
 
-use crate::field::{GameState, TileContent};
-use crate::Game;
+use crate::field::{FirstClickPolicy, Game, GameState, Hint};
+use crate::preset::{self, Preset, MAX_SIZE, MIN_MINES, MIN_SIZE};
+use crate::probability::mine_probabilities;
+use crate::render::{Overlay, Renderer, ThemeSource};
+use crate::replay::{self, Playback};
+use crate::results::{Ended, Record, Results};
+use crate::save;
+use crate::scores::MAX_NAME_LENGTH;
+use crate::stats::BoardStats;
+
+// One row per preset and a last row for custom settings.
+const MENU_ROWS: usize = Preset::ALL.len() + 1;
+const DIGIT_KEYS: [(Keycode, Keycode); 10] = [
+    (Keycode::Num0, Keycode::Kp0),
+    (Keycode::Num1, Keycode::Kp1),
+    (Keycode::Num2, Keycode::Kp2),
+    (Keycode::Num3, Keycode::Kp3),
+    (Keycode::Num4, Keycode::Kp4),
+    (Keycode::Num5, Keycode::Kp5),
+    (Keycode::Num6, Keycode::Kp6),
+    (Keycode::Num7, Keycode::Kp7),
+    (Keycode::Num8, Keycode::Kp8),
+    (Keycode::Num9, Keycode::Kp9),
+];
+
+/// Largest factor the board can be scaled up by.
+pub const MAX_SCALE: u32 = 4;
+
+/// Integer factor the board is scaled up by, or picked from the display DPI.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scale {
+    Auto,
+    Fixed(u32),
+}
+
+impl Scale {
+    /// `auto` or a factor from 1 to [`MAX_SCALE`].
+    pub fn from_name(name: &str) -> Option<Scale> {
+        match name {
+            "auto" => Some(Scale::Auto),
+            _ => name
+                .parse()
+                .ok()
+                .filter(|n| (1..=MAX_SCALE).contains(n))
+                .map(Scale::Fixed),
+        }
+    }
+}
+
+/// Where the canvas is shown in a window: the largest whole multiple of its
+/// size that fits, centered.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+struct Viewport {
+    x: i32,
+    y: i32,
+    w: u32,
+    h: u32,
+    scale: u32,
+}
+
+impl Viewport {
+    fn fit(canvas: (u32, u32), window: (u32, u32)) -> Viewport {
+        let scale = (window.0 / canvas.0).min(window.1 / canvas.1).max(1);
+        let (w, h) = (canvas.0 * scale, canvas.1 * scale);
+        Viewport {
+            x: (window.0 as i32 - w as i32) / 2,
+            y: (window.1 as i32 - h as i32) / 2,
+            w,
+            h,
+            scale,
+        }
+    }
+
+    fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.w, self.h)
+    }
+
+    /// Canvas pixel under a point in the window.
+    fn canvas_point(&self, x: i32, y: i32) -> (i32, i32) {
+        let scale = self.scale as i32;
+        (
+            (x - self.x).div_euclid(scale),
+            (y - self.y).div_euclid(scale),
+        )
+    }
+}
+
+/// How to reveal the neighbours of a number whose mines are all flagged.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ChordStyle {
+    /// Left click on the number.
+    LeftClick,
+    /// Left and right button held together, released over the number.
+    BothButtons,
+    /// Middle click on the number.
+    MiddleClick,
+    Disabled,
+}
+
+impl ChordStyle {
+    pub const ALL: [ChordStyle; 4] = [
+        ChordStyle::LeftClick,
+        ChordStyle::BothButtons,
+        ChordStyle::MiddleClick,
+        ChordStyle::Disabled,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ChordStyle::LeftClick => "left",
+            ChordStyle::BothButtons => "both",
+            ChordStyle::MiddleClick => "middle",
+            ChordStyle::Disabled => "off",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ChordStyle> {
+        ChordStyle::ALL.into_iter().find(|s| s.name() == name)
+    }
+}
 
 pub struct Drawer<'a> {
     // sdl_context: sdl2::Sdl,
     window: sdl2::video::Window,
     event_pump: sdl2::EventPump,
-    assets: Assets<'a>,
+    renderer: Renderer<'a>,
+    /// The screen at 1x, scaled up to the window.
+    canvas: Surface<'a>,
+    /// Factor the window is sized to when the board changes.
+    scale: u32,
+    theme: ThemeSource,
+    /// Themes to cycle through at runtime.
+    themes: Vec<ThemeSource>,
     mouse_down: bool,
+    right_down: bool,
+    middle_down: bool,
+    chorded: bool,
+    chord_style: ChordStyle,
+    /// Tile selected with the keyboard, hidden until a cursor key is used.
+    cursor: Option<(usize, usize)>,
+    title: String,
+    show_probabilities: bool,
+    probabilities: Option<Vec<Vec<Option<f64>>>>,
+    probabilities_for: (i32, Vec<(bool, bool)>),
+    hint: Option<Hint>,
+    save_file: PathBuf,
+    playback: Option<String>,
+    menu: Option<Menu>,
+    results: Results,
+    record: Option<Record>,
+    stats: Option<BoardStats>,
 }
 
-pub struct Assets<'a> {
-    pub borders: Surface<'a>,
-    pub numbers: Surface<'a>,
-    pub faces: Surface<'a>,
-    pub tiles: Surface<'a>,
+/// Difficulty menu. The custom row holds width, height and mine count, and
+/// `column` is the one being edited. New games keep the first click policy.
+struct Menu {
+    row: usize,
+    column: usize,
+    custom: [u32; 3],
+    policy: FirstClickPolicy,
 }
 
-impl<'a> Assets<'a> {
-    pub fn load() -> Assets<'a> {
-        let borders = Surface::load_bmp("assets\\borders.bmp").expect("Cannot load surface!");
-        let numbers = Surface::load_bmp("assets\\numbers.bmp").expect("Cannot load surface!");
-        let faces = Surface::load_bmp("assets\\faces.bmp").expect("Cannot load surface!");
-        let tiles = Surface::load_bmp("assets\\tiles.bmp").expect("Cannot load surface!");
+impl Menu {
+    fn new(game: &Game) -> Menu {
+        let settings = (game.width(), game.height(), game.mine_count());
+        let row = Preset::ALL
+            .iter()
+            .position(|p| p.settings() == settings)
+            .unwrap_or(MENU_ROWS - 1);
+        Menu {
+            row,
+            column: 0,
+            custom: [
+                game.width() as u32,
+                game.height() as u32,
+                game.mine_count() as u32,
+            ],
+            policy: game.first_click_policy(),
+        }
+    }
 
-        Assets {
-            borders,
-            numbers,
-            faces,
-            tiles,
+    fn settings(&self, row: usize) -> (usize, usize, i32) {
+        match Preset::ALL.get(row) {
+            Some(preset) => preset.settings(),
+            None => {
+                let [w, h, mc] = self.custom;
+                (w as usize, h as usize, mc as i32)
+            }
+        }
+    }
+
+    /// Which of the custom values are out of range.
+    fn invalid(&self) -> [bool; 3] {
+        let (w, h, mc) = self.settings(MENU_ROWS - 1);
+        let size_ok = |n: usize| (MIN_SIZE..=MAX_SIZE).contains(&n);
+        let mines_ok = if size_ok(w) && size_ok(h) {
+            preset::check_settings(w, h, mc, self.policy).is_ok()
+        } else {
+            mc >= MIN_MINES
+        };
+        [!size_ok(w), !size_ok(h), !mines_ok]
+    }
+
+    fn label(&self) -> String {
+        let (w, h, mc) = self.settings(self.row);
+        match Preset::ALL.get(self.row) {
+            Some(preset) => format!(
+                "New {} game: {}x{}, {} mines - Enter to start",
+                preset.name(),
+                w,
+                h,
+                mc
+            ),
+            None => {
+                let value = ["width", "height", "mines"][self.column];
+                format!("Custom game: editing {} - Enter to start", value)
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: Keycode) {
+        let custom = self.row == MENU_ROWS - 1;
+        match key {
+            Keycode::Up => self.row = (self.row + MENU_ROWS - 1) % MENU_ROWS,
+            Keycode::Down => self.row = (self.row + 1) % MENU_ROWS,
+            Keycode::Left if custom => self.column = (self.column + 2) % 3,
+            Keycode::Right | Keycode::Tab if custom => self.column = (self.column + 1) % 3,
+            Keycode::Backspace if custom => self.custom[self.column] /= 10,
+            _ => {
+                let digit = DIGIT_KEYS.iter().position(|&(a, b)| key == a || key == b);
+                if let (Some(digit), true) = (digit, custom) {
+                    // The counters show three digits, so typing a fourth
+                    // starts the value over.
+                    let value = &mut self.custom[self.column];
+                    *value = if *value >= 100 {
+                        digit as u32
+                    } else {
+                        *value * 10 + digit as u32
+                    };
+                }
+            }
         }
     }
 }
 
 impl<'a> Drawer<'a> {
-    pub fn initialize_game(w: u32, h: u32) -> Drawer<'a> {
+    /// Opens the game window for a board of `w` by `h` tiles.
+    pub fn initialize_game(w: u32, h: u32, theme: ThemeSource) -> Drawer<'a> {
+        let renderer = Renderer::new(&theme).unwrap_or_else(|err| {
+            println!("{}", err);
+            std::process::exit(1);
+        });
+
         let sdl_context = sdl2::init().unwrap_or_else(|err| {
             println!("Cannot initialize SDL! {}", err);
             std::process::exit(1);
@@ -50,9 +268,11 @@ impl<'a> Drawer<'a> {
             .video()
             .expect("Cannot initialize video for SDL!");
 
+        let (width, height) = renderer.screen_size(w as usize, h as usize);
         let mut window = video_subsystem
-            .window("rust-sdl2 demo", (w * 16 + 8) as u32, (h * 16 + 44) as u32)
+            .window("rust-sdl2 demo", width, height)
             .position_centered()
+            .resizable()
             .build()
             .expect("Cannot initialize video mode for SDL! {}");
 
@@ -64,235 +284,566 @@ impl<'a> Drawer<'a> {
             .event_pump()
             .expect("Cannot initialize event pump for SDL!");
 
-        let assets = Assets::load();
+        let canvas = Surface::new(width, height, window.window_pixel_format())
+            .expect("Cannot create canvas surface!");
 
         let d = Drawer {
             // sdl_context,
             window,
             event_pump,
-            assets,
+            renderer,
+            canvas,
+            scale: 1,
+            themes: vec![theme.clone()],
+            theme,
             mouse_down: false,
+            right_down: false,
+            middle_down: false,
+            chorded: false,
+            chord_style: ChordStyle::LeftClick,
+            cursor: None,
+            title: String::new(),
+            show_probabilities: false,
+            probabilities: None,
+            probabilities_for: (0, vec![]),
+            hint: None,
+            save_file: PathBuf::from("minesweeper.sav"),
+            playback: None,
+            menu: None,
+            results: Results::default(),
+            record: None,
+            stats: None,
         };
 
         d
     }
 
-    pub fn draw_screen(&mut self, i: f64, game: &Game) {
-        let mut screen = self
-            .window
-            .surface(&self.event_pump)
-            .expect("Cannot obtain window surface!");
+    /// Themes `F11` cycles through. The current theme is added if missing.
+    pub fn set_themes(&mut self, themes: Vec<ThemeSource>) {
+        self.themes = themes;
+        if !self.themes.contains(&self.theme) {
+            self.themes.insert(0, self.theme.clone());
+        }
+    }
 
-        let color = Color::RGB(
-            (((i).cos() + 1.0) * 126.0).round() as u8,
-            64,
-            (((i).sin() + 1.0) * 126.0).round() as u8,
-        );
+    pub fn set_chord_style(&mut self, style: ChordStyle) {
+        self.chord_style = style;
+    }
 
-        screen.fill_rect(None, color).unwrap();
+    pub fn set_save_file(&mut self, path: PathBuf) {
+        self.save_file = path;
+    }
 
-        self.draw_borders(&mut screen, game);
-        self.draw_field(&mut screen, game);
-        self.draw_numbers(&mut screen, game);
-        self.draw_face(&mut screen, game);
+    fn save_game(&self, game: &Game) {
+        match save::save(game, &self.save_file) {
+            Ok(()) => println!("Game saved to {}", self.save_file.display()),
+            Err(err) => println!("Cannot save game to {}! {}", self.save_file.display(), err),
+        }
+    }
 
-        screen.finish().unwrap();
+    fn load_game(&mut self, game: &mut Game) {
+        match save::load(&self.save_file) {
+            Ok(loaded) => {
+                *game = loaded;
+                self.reset_game();
+                self.results.loaded(game);
+                self.fit_window(game);
+                println!("Game loaded from {}", self.save_file.display());
+            }
+            Err(err) => println!(
+                "Cannot load game from {}! {}",
+                self.save_file.display(),
+                err
+            ),
+        }
     }
 
-    fn draw_borders(self: &Self, screen: &mut sdl2::video::WindowSurfaceRef, game: &Game) {
-        let (w, h) = screen.size();
-        let (w, h) = (w as i32, h as i32);
-        self.assets
-            .borders
-            .blit(Rect::new(0, 0, 52, 40), screen, Rect::new(0, 0, 52, 40))
-            .unwrap();
+    fn handle_menu_key(&mut self, key: Keycode, game: &mut Game) {
+        let menu = match self.menu.as_mut() {
+            Some(menu) => menu,
+            None => return,
+        };
+        match key {
+            Keycode::Escape | Keycode::F6 => self.menu = None,
+            Keycode::Return | Keycode::KpEnter => {
+                let (w, h, mc) = menu.settings(menu.row);
+                match preset::check_settings(w, h, mc, menu.policy) {
+                    Ok(()) => match Game::with_policy(w, h, mc, rand::random(), menu.policy) {
+                        Ok(new_game) => {
+                            let question_marks = game.question_marks();
+                            *game = new_game;
+                            game.set_question_marks(question_marks);
+                            self.menu = None;
+                            self.reset_game();
+                            self.fit_window(game);
+                        }
+                        Err(err) => println!("{}", err),
+                    },
+                    Err(err) => println!("{}", err),
+                }
+            }
+            _ => menu.handle_key(key),
+        }
+    }
 
-        self.assets
-            .borders
-            .blit(
-                Rect::new(106, 0, 52, 40),
-                screen,
-                Rect::new(w - 52, 0, 52, 40),
-            )
-            .unwrap();
+    /// Moves the keyboard cursor or acts on the tile under it. The first
+    /// cursor key only shows the cursor in the middle of the board.
+    fn handle_cursor_key(&mut self, key: Keycode, keymod: Mod, game: &mut Game) {
+        let (w, h) = (game.width(), game.height());
+        let (x, y) = match self.cursor.filter(|&(x, y)| x < w && y < h) {
+            Some(cursor) => cursor,
+            None => {
+                if Drawer::is_cursor_key(key) {
+                    self.cursor = Some((w / 2, h / 2));
+                }
+                return;
+            }
+        };
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        self.cursor = Some(match key {
+            Keycode::Left | Keycode::A | Keycode::H => (x.saturating_sub(1), y),
+            Keycode::Right | Keycode::D | Keycode::L => ((x + 1).min(w - 1), y),
+            Keycode::Up | Keycode::W | Keycode::K => (x, y.saturating_sub(1)),
+            Keycode::Down | Keycode::S | Keycode::J => (x, (y + 1).min(h - 1)),
+            Keycode::N => game.next_unrevealed(x, y, !shift).unwrap_or((x, y)),
+            Keycode::Space | Keycode::Return | Keycode::KpEnter => {
+                if !game.get_field()[x][y].revealed {
+                    let _ = game.reveal_tile(x, y);
+                } else if self.chord_style == ChordStyle::LeftClick {
+                    let _ = game.chord_tile(x, y);
+                }
+                (x, y)
+            }
+            Keycode::F => {
+                let _ = game.flag_tile(x, y);
+                (x, y)
+            }
+            Keycode::C => {
+                let _ = game.chord_tile(x, y);
+                (x, y)
+            }
+            _ => (x, y),
+        });
+    }
 
-        self.assets
-            .borders
-            .blit(Rect::new(0, 60, 4, 4), screen, Rect::new(0, h - 4, 4, 4))
-            .unwrap();
-        self.assets
-            .borders
-            .blit(
-                Rect::new(24, 60, 4, 4),
-                screen,
-                Rect::new(w - 4, h - 4, 4, 4),
-            )
-            .unwrap();
+    fn is_cursor_key(key: Keycode) -> bool {
+        matches!(
+            key,
+            Keycode::Left
+                | Keycode::Right
+                | Keycode::Up
+                | Keycode::Down
+                | Keycode::A
+                | Keycode::D
+                | Keycode::W
+                | Keycode::S
+                | Keycode::H
+                | Keycode::J
+                | Keycode::K
+                | Keycode::L
+                | Keycode::N
+                | Keycode::Space
+                | Keycode::Return
+                | Keycode::KpEnter
+                | Keycode::F
+                | Keycode::C
+        )
+    }
 
-        self.assets
-            .borders
-            .blit(
-                Rect::new(72, 0, 32, 40),
-                screen,
-                Rect::new(w / 2 - 16, 0, 32, 40),
-            )
-            .unwrap();
+    /// Forgets everything about the previous game.
+    fn reset_game(&mut self) {
+        self.hint = None;
+        self.results.new_game();
+    }
 
-        for y in 0..game.height() {
-            let y = y as i32;
-            self.assets
-                .borders
-                .blit(
-                    Rect::new(0, 42, 4, 16),
-                    screen,
-                    Rect::new(0, 40 + y * 16, 4, 16),
-                )
-                .unwrap();
-            self.assets
-                .borders
-                .blit(
-                    Rect::new(24, 42, 4, 16),
-                    screen,
-                    Rect::new(w - 4, 40 + y * 16, 4, 16),
-                )
-                .unwrap();
-        }
-
-        for x in 0..game.width() {
-            let x = x as i32;
-            self.assets
-                .borders
-                .blit(
-                    Rect::new(6, 60, 16, 4),
-                    screen,
-                    Rect::new(4 + x * 16, h - 4, 16, 4),
-                )
-                .unwrap();
-        }
-
-        let rem = w / 2 - 52 - 32 / 2;
-        for x in 0..rem / 16 {
-            let x = x as i32;
-            self.assets
-                .borders
-                .blit(
-                    Rect::new(54, 0, 16, 40),
-                    screen,
-                    Rect::new(52 + x * 16, 0, 16, 40),
-                )
-                .unwrap();
-
-            self.assets
-                .borders
-                .blit(
-                    Rect::new(54, 0, 16, 40),
-                    screen,
-                    Rect::new(w / 2 + 16 + x * 16, 0, 16, 40),
-                )
-                .unwrap();
-        }
-    }
-
-    fn draw_field(&self, screen: &mut sdl2::video::WindowSurfaceRef, game: &Game) {
-        let field = game.get_field();
-
-        for (x, row) in field.iter().enumerate() {
-            for (y, tile) in row.iter().enumerate() {
-                let rect = match (&tile.revealed, &tile.flagged, &tile.content) {
-                    (true, false, TileContent::Bomb) => Rect::new(18, 18, 16, 16),
-                    (true, true, TileContent::Empty(_)) => Rect::new(36, 36, 16, 16),
-                    (true, true, TileContent::Bomb) => Rect::new(18, 36, 16, 16),
-                    (true, false, TileContent::Empty(i)) => Rect::new(i * 18, 0, 16, 16),
-                    (false, false, TileContent::Bomb) => {
-                        if matches!(game.state(), GameState::Playing(_)) {
-                            if game.preview_at(x, y) {
-                                Rect::new(0, 0, 16, 16)
-                            } else {
-                                Rect::new(0, 36, 16, 16)
-                            }
-                        } else {
-                            Rect::new(0, 18, 16, 16)
-                        }
-                    }
-                    (false, false, _) => {
-                        if game.preview_at(x, y) {
-                            Rect::new(0, 0, 16, 16)
-                        } else {
-                            Rect::new(0, 36, 16, 16)
-                        }
-                    }
+    fn check_finished(&mut self, game: &Game) {
+        if let Some(ended) = self.results.update(game) {
+            self.report(ended);
+        }
+    }
+
+    fn report(&mut self, ended: Ended) {
+        match ended.replay {
+            Ok(path) => println!("Replay saved to {}", path.display()),
+            Err(err) => println!(
+                "Cannot save replay to {}! {}",
+                self.results.replay_dir.display(),
+                err
+            ),
+        }
+        if let Err(err) = ended.stats {
+            println!(
+                "Cannot save statistics to {}! {}",
+                self.results.stats_file.display(),
+                err
+            );
+        }
+        match ended.record {
+            // Starts name entry.
+            Ok(record) => self.record = record,
+            Err(err) => println!(
+                "Cannot load scores from {}! {}",
+                self.results.scores_file.display(),
+                err
+            ),
+        }
+    }
+
+    fn show_stats(&mut self, game: &Game) {
+        match self
+            .results
+            .stats((game.width(), game.height(), game.mine_count()))
+        {
+            Ok(stats) => self.stats = Some(stats),
+            Err(err) => println!(
+                "Cannot load statistics from {}! {}",
+                self.results.stats_file.display(),
+                err
+            ),
+        }
+    }
 
-                    (false, true, _) => Rect::new(18, 36, 16, 16),
-                    // (_, _, _) => Rect::new(36, 18, 16, 16),
-                };
-
-                self.assets
-                    .tiles
-                    .blit(
-                        rect,
-                        screen,
-                        Rect::new(x as i32 * 16 + 4, y as i32 * 16 + 40, 16, 16),
-                    )
-                    .unwrap();
+    fn handle_record_event(&mut self, event: Event) {
+        let record = match self.record.as_mut() {
+            Some(record) => record,
+            None => return,
+        };
+        match event {
+            Event::TextInput { text, .. } => {
+                let room = MAX_NAME_LENGTH - record.name.chars().count();
+                record.name.extend(text.chars().take(room));
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Backspace),
+                ..
+            } => {
+                record.name.pop();
+            }
+            Event::KeyDown {
+                keycode: Some(Keycode::Return | Keycode::KpEnter),
+                ..
+            } => {
+                if let Some(record) = self.record.take() {
+                    self.save_record(record);
+                }
             }
+            Event::KeyDown {
+                keycode: Some(Keycode::Escape),
+                ..
+            } => self.record = None,
+            _ => {}
+        }
+    }
+
+    fn save_record(&self, record: Record) {
+        if let Err(err) = self.results.save_record(&record) {
+            println!(
+                "Cannot save scores to {}! {}",
+                self.results.scores_file.display(),
+                err
+            );
         }
     }
 
-    fn draw_numbers(&self, screen: &mut sdl2::video::WindowSurfaceRef, game: &Game) {
-        self.draw_number(screen, 35, 9, game.mines_remaining() as i32);
-        self.draw_number(screen, (screen.width() - 19) as i32, 9, game.time());
+    fn fit_window(&mut self, game: &Game) {
+        let (w, h) = self.renderer.screen_size(game.width(), game.height());
+        self.canvas = Surface::new(w, h, self.window.window_pixel_format())
+            .expect("Cannot create canvas surface!");
+        self.window
+            .set_minimum_size(w, h)
+            .expect("Cannot resize window!");
+        self.window
+            .set_size(w * self.scale, h * self.scale)
+            .expect("Cannot resize window!");
     }
 
-    fn draw_number(&self, screen: &mut sdl2::video::WindowSurfaceRef, x: i32, y: i32, number: i32) {
-        let mut number = number;
-        for i in 0..3 {
-            let n = number % 10;
-            number /= 10;
+    fn viewport(&self) -> Viewport {
+        Viewport::fit(self.canvas.size(), self.window.size())
+    }
+
+    pub fn set_scale(&mut self, scale: Scale, game: &Game) {
+        self.scale = match scale {
+            Scale::Fixed(n) => n.clamp(1, MAX_SCALE),
+            Scale::Auto => self.display_scale(),
+        };
+        self.fit_window(game);
+    }
 
-            self.assets
-                .numbers
-                .blit(
-                    Rect::new(n * 12, 0, 10, 18),
-                    screen,
-                    Rect::new(x - i * 13, y, 10, 18),
-                )
-                .unwrap();
+    /// Scale for the display the window is on, counting 96 DPI as 1x.
+    fn display_scale(&self) -> u32 {
+        let display = self.window.display_index().unwrap_or(0);
+        match self.window.subsystem().display_dpi(display) {
+            Ok((dpi, _, _)) => ((dpi / 96.0).round() as u32).clamp(1, MAX_SCALE),
+            Err(_) => 1,
         }
     }
 
-    fn draw_face(&self, screen: &mut sdl2::video::WindowSurfaceRef, game: &Game) {
-        let offset = match game.state() {
-            GameState::Boom(_) => 2 * 24,
-            GameState::Victory(_) => 1 * 24,
-            GameState::Playing(_) => {
-                if game.preview() {
-                    3 * 24
-                } else {
-                    0
-                }
+    /// Switches to the theme after the current one.
+    fn next_theme(&mut self, game: &Game) {
+        let current = self.themes.iter().position(|t| *t == self.theme);
+        let next = current.map_or(0, |i| (i + 1) % self.themes.len());
+        let theme = self.themes[next].clone();
+        match Renderer::new(&theme) {
+            Ok(renderer) => {
+                self.renderer = renderer;
+                self.theme = theme;
+                self.fit_window(game);
+                println!("Theme: {}", self.renderer.theme().name);
             }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    pub fn draw_screen(&mut self, i: f64, game: &Game) {
+        self.update_title(game);
+        if self.show_probabilities {
+            self.update_probabilities(game);
+        }
+
+        let overlay = Overlay {
+            probabilities: self
+                .probabilities
+                .as_deref()
+                .filter(|_| self.show_probabilities),
+            hint: self.hint,
+            cursor: self.cursor,
         };
+        let viewport = self.viewport();
+        let canvas = &mut self.canvas;
+        Renderer::draw_background(canvas, i);
+        self.renderer.draw_board(canvas, game, &overlay);
+        if let Some(menu) = &self.menu {
+            Drawer::draw_menu(&self.renderer, canvas, menu);
+        }
+        if let Some(stats) = &self.stats {
+            Drawer::draw_stats(canvas, stats);
+        }
 
-        let w = screen.width() as i32;
-        self.assets
-            .faces
-            .blit(
-                Rect::new(offset, 0, 22, 22),
-                screen,
-                Rect::new(w / 2 - 11, 7, 22, 22),
-            )
+        let mut screen = self
+            .window
+            .surface(&self.event_pump)
+            .expect("Cannot obtain window surface!");
+        screen.fill_rect(None, Color::RGB(0, 0, 0)).unwrap();
+        canvas
+            .blit_scaled(None, &mut screen, viewport.rect())
             .unwrap();
+        screen.finish().unwrap();
+    }
+
+    fn update_title(&mut self, game: &Game) {
+        let mut title = match (game.first_click_policy(), game.generation_attempts()) {
+            (FirstClickPolicy::Opening, _) => format!("Minesweeper in Rust (seed {})", game.seed()),
+            (FirstClickPolicy::NoGuess, n) if game.guess_free() => format!(
+                "Minesweeper in Rust (seed {}, no-guess board in {} attempts)",
+                game.seed(),
+                n
+            ),
+            (FirstClickPolicy::NoGuess, n) if n > 0 => format!(
+                "Minesweeper in Rust (seed {}, no no-guess board in {} attempts, may need guessing)",
+                game.seed(),
+                n
+            ),
+            (policy, _) => format!(
+                "Minesweeper in Rust (seed {}, first click: {})",
+                game.seed(),
+                policy.name()
+            ),
+        };
+        if let Some(result) = Drawer::result_text(game) {
+            title = format!("{} - {}", result, title);
+        }
+        if let Some(playback) = &self.playback {
+            title = format!("{} - {}", playback, title);
+        }
+        if let Some(menu) = &self.menu {
+            title = menu.label();
+        }
+        if let Some(stats) = &self.stats {
+            title = Drawer::stats_text(game, stats);
+        }
+        if let Some(record) = &self.record {
+            title = format!(
+                "New record {:.3} s! Type your name and press Enter: {}_",
+                record.time.as_secs_f64(),
+                record.name
+            );
+        }
+        if title == self.title {
+            return;
+        }
+        self.window
+            .set_title(&title)
+            .expect("Failed to set window title!");
+        self.title = title;
+    }
+
+    fn stats_text(game: &Game, stats: &BoardStats) -> String {
+        let (w, h, mc) = (game.width(), game.height(), game.mine_count());
+        let name = Preset::find(w, h, mc).map_or("custom", |p| p.name());
+        let mut text = format!(
+            "Statistics for {} {}x{}, {} mines: {} played, {} won ({:.1}%), streak {}, longest {}",
+            name,
+            w,
+            h,
+            mc,
+            stats.played,
+            stats.won,
+            stats.win_rate() * 100.0,
+            stats.current_streak,
+            stats.longest_streak
+        );
+        if let Some(average) = stats.average_win_time() {
+            text += &format!(", average {:.3} s", average.as_secs_f64());
+        }
+        text
+    }
+
+    fn result_text(game: &Game) -> Option<String> {
+        let mut text = match game.state() {
+            GameState::Victory(t) => format!("Cleared in {:.3} s", t.as_secs_f64()),
+            GameState::Boom(t) => format!("Boom after {:.3} s", t.as_secs_f64()),
+            GameState::Playing(_) => return None,
+        };
+        if let Some(m) = game.metrics() {
+            text += &format!(
+                ", 3BV {}, {:.2} 3BV/s, {} clicks, {:.0}% efficiency",
+                m.three_bv,
+                m.three_bv_per_second,
+                m.clicks.total(),
+                m.efficiency
+            );
+        }
+        if game.hints_used() > 0 {
+            text += &format!(", {} hints used", game.hints_used());
+        }
+        if game.assisted() {
+            text += ", assisted with undo";
+        }
+        Some(text)
+    }
+
+    fn update_probabilities(&mut self, game: &Game) {
+        let visible = game
+            .get_field()
+            .iter()
+            .flatten()
+            .map(|t| (t.revealed, t.flagged))
+            .collect();
+        let key = (game.mine_count(), visible);
+        if key != self.probabilities_for {
+            self.probabilities = mine_probabilities(game);
+            self.probabilities_for = key;
+            if self.probabilities.is_none() {
+                println!("Cannot compute mine probabilities for this board.");
+            }
+        }
+    }
+
+    /// Draws an empty panel with room for `rows` rows of counters, centered
+    /// on the field.
+    fn draw_panel(screen: &mut SurfaceRef, rows: usize) -> Rect {
+        let (w, h) = screen.size();
+        let height = rows as i32 * 24 + 8;
+        let panel = Rect::new(
+            w as i32 / 2 - 64,
+            (h as i32 + 36 - height) / 2,
+            128,
+            height as u32,
+        );
+        screen.fill_rect(panel, Color::RGB(192, 192, 192)).unwrap();
+        Renderer::draw_frame(screen, panel, 1, Color::RGB(128, 128, 128));
+        panel
+    }
+
+    fn draw_menu(renderer: &Renderer, screen: &mut SurfaceRef, menu: &Menu) {
+        let panel = Drawer::draw_panel(screen, MENU_ROWS);
+
+        let invalid = menu.invalid();
+        for row in 0..MENU_ROWS {
+            let y = panel.y() + 4 + row as i32 * 24;
+            if row == menu.row {
+                let rect = Rect::new(panel.x() + 1, y, 126, 24);
+                Renderer::draw_frame(screen, rect, 1, Color::RGB(255, 255, 255));
+            }
+
+            let (w, h, mc) = menu.settings(row);
+            for (i, value) in [w as i32, h as i32, mc].into_iter().enumerate() {
+                let x = panel.x() + 4 + i as i32 * 42;
+                if row == MENU_ROWS - 1 {
+                    let editing = row == menu.row && i == menu.column;
+                    let color = match (invalid[i], editing) {
+                        (true, true) => Some(Color::RGB(255, 160, 160)),
+                        (true, false) => Some(Color::RGB(224, 0, 0)),
+                        (false, true) => Some(Color::RGB(255, 255, 255)),
+                        (false, false) => None,
+                    };
+                    if let Some(color) = color {
+                        Renderer::draw_frame(screen, Rect::new(x - 2, y + 1, 40, 22), 2, color);
+                    }
+                }
+                renderer.draw_number(screen, x + 26, y + 3, value);
+            }
+        }
+    }
+
+    /// One labelled line per statistic, with the exact average winning time.
+    fn draw_stats(screen: &mut SurfaceRef, stats: &BoardStats) {
+        let panel = Drawer::draw_panel(screen, 3);
+        let average = stats
+            .average_win_time()
+            .map_or("-".to_string(), |t| format!("{:.1} s", t.as_secs_f64()));
+        let rows = [
+            ("played", stats.played.to_string()),
+            ("won", stats.won.to_string()),
+            ("win rate", format!("{:.0}%", stats.win_rate() * 100.0)),
+            ("average", average),
+            ("streak", stats.current_streak.to_string()),
+            ("longest", stats.longest_streak.to_string()),
+        ];
+        let color = Color::RGB(0, 0, 0);
+        for (row, (label, value)) in rows.iter().enumerate() {
+            let y = panel.y() + 5 + row as i32 * 12;
+            Renderer::draw_text(screen, panel.x() + 5, y, label, 2, color);
+            let x = panel.right() - 5 - Renderer::text_width(value, 2) as i32;
+            Renderer::draw_text(screen, x, y, value, 2, color);
+        }
     }
 
     pub fn handle_events(&mut self, game: &mut Game) -> bool {
-        let w = self
-            .window
-            .surface(&self.event_pump)
-            .expect("Cannot obtain window surface!")
-            .size()
-            .0 as i32;
+        let w = self.canvas.width();
+        let viewport = self.viewport();
+
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+            if self.record.is_some() {
+                if let Event::Quit { .. } = event {
+                    return true;
+                }
+                self.handle_record_event(event);
+                continue;
+            }
+            if self.stats.is_some() {
+                match event {
+                    Event::Quit { .. } => return true,
+                    Event::KeyDown {
+                        keycode: Some(Keycode::Escape | Keycode::F7),
+                        ..
+                    } => self.stats = None,
+                    _ => {}
+                }
+                continue;
+            }
+            if self.menu.is_some() {
+                match event {
+                    Event::Quit { .. } => return true,
+                    Event::KeyDown {
+                        keycode: Some(key), ..
+                    } => self.handle_menu_key(key, game),
+                    _ => {}
+                }
+                continue;
+            }
 
-        for event in self.event_pump.poll_iter() {
             match event {
                 Event::Quit { .. }
                 | Event::KeyDown {
@@ -302,67 +853,240 @@ impl<'a> Drawer<'a> {
                 Event::KeyDown {
                     keycode: Some(Keycode::F2),
                     ..
-                } => game.new_game(),
-                Event::MouseButtonDown {
-                    mouse_btn: MouseButton::Right,
-                    x,
-                    y,
+                } => {
+                    self.reset_game();
+                    game.new_game();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F3),
+                    ..
+                } => self.show_probabilities = !self.show_probabilities,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F4),
+                    ..
+                } => self.hint = game.hint(),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F5),
+                    ..
+                } => self.save_game(game),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F6),
                     ..
                 } => {
-                    let x = (x - 4) / 16;
-                    let y = (y - 40) / 16;
-                    if x >= 0 && x < game.width() as i32 && y >= 0 && y < game.height() as i32 {
-                        game.flag_tile(x as usize, y as usize);
-                    }
+                    self.release_mouse(game);
+                    self.menu = Some(Menu::new(game));
                 }
-                Event::MouseButtonDown {
-                    mouse_btn: MouseButton::Left,
-                    x,
-                    y,
+                Event::KeyDown {
+                    keycode: Some(Keycode::F7),
+                    ..
+                } => {
+                    self.release_mouse(game);
+                    self.show_stats(game);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F8),
                     ..
                 } => {
-                    self.mouse_down = true;
-                    if x >= w / 2 - 11 && y >= 7 && x < w / 2 + 11 && y < 29 {
+                    let enabled = !game.question_marks();
+                    game.set_question_marks(enabled);
+                    println!("Question marks {}", if enabled { "on" } else { "off" });
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F9),
+                    ..
+                } => self.load_game(game),
+                Event::KeyDown {
+                    keycode: Some(Keycode::F10),
+                    ..
+                } => {
+                    let all = ChordStyle::ALL;
+                    let next = all.iter().position(|&s| s == self.chord_style).unwrap() + 1;
+                    self.chord_style = all[next % all.len()];
+                    println!("Chord style: {}", self.chord_style.name());
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::F11),
+                    ..
+                } => self.next_theme(game),
+                Event::KeyDown {
+                    keycode: Some(key @ (Keycode::Equals | Keycode::Plus | Keycode::Minus)),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let scale = match key {
+                        Keycode::Minus => viewport.scale.saturating_sub(1),
+                        _ => viewport.scale + 1,
+                    };
+                    self.set_scale(Scale::Fixed(scale), game);
+                    println!("Scale: {}x", self.scale);
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Z),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    game.undo();
+                }
+                Event::KeyDown {
+                    keycode: Some(Keycode::Y),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    game.redo();
+                }
+                Event::KeyDown {
+                    keycode: Some(key),
+                    keymod,
+                    ..
+                } => self.handle_cursor_key(key, keymod, game),
+                Event::MouseButtonDown {
+                    mouse_btn, x, y, ..
+                } => {
+                    let (x, y) = viewport.canvas_point(x, y);
+                    let face = self.renderer.face_rect(w);
+                    if mouse_btn == MouseButton::Left && face.contains_point((x, y)) {
+                        self.mouse_down = true;
+                        self.reset_game();
                         game.new_game();
                     } else {
-                        let x = (x - 4) / 16;
-                        let y = (y - 40) / 16;
-                        if x >= 0 && x < game.width() as i32 && y >= 0 && y < game.height() as i32 {
-                            game.set_preview(Some((x as usize, y as usize)));
-                        }
+                        self.mouse_pressed(game, mouse_btn, x, y);
                     }
                 }
                 Event::MouseButtonUp {
-                    mouse_btn: MouseButton::Left,
-                    x,
-                    y,
-                    ..
+                    mouse_btn, x, y, ..
                 } => {
-                    game.set_preview(None);
-                    self.mouse_down = false;
-                    let x = (x - 4) / 16;
-                    let y = (y - 40) / 16;
-                    if x >= 0 && x < game.width() as i32 && y >= 0 && y < game.height() as i32 {
-                        game.reveal_tile(x as usize, y as usize);
-                    }
+                    let (x, y) = viewport.canvas_point(x, y);
+                    self.mouse_released(game, mouse_btn, x, y)
                 }
                 Event::MouseMotion { x, y, .. } => {
-                    let x = (x - 4) / 16;
-                    let y = (y - 40) / 16;
-                    if self.mouse_down
-                        && x >= 0
-                        && x < game.width() as i32
-                        && y >= 0
-                        && y < game.height() as i32
-                    {
-                        game.set_preview(Some((x as usize, y as usize)));
-                    } else {
-                        game.set_preview(None);
+                    let (x, y) = viewport.canvas_point(x, y);
+                    self.update_preview(game, x, y)
+                }
+                _ => {}
+            }
+        }
+        self.check_finished(game);
+        false
+    }
+
+    /// Forgets the buttons held down when a dialog takes over the mouse.
+    fn release_mouse(&mut self, game: &mut Game) {
+        self.mouse_down = false;
+        self.right_down = false;
+        self.middle_down = false;
+        self.chorded = false;
+        game.set_preview(None);
+    }
+
+    /// Whether the buttons held down make up a chord in the chosen style.
+    fn chord_held(&self) -> bool {
+        match self.chord_style {
+            ChordStyle::BothButtons => self.mouse_down && self.right_down,
+            ChordStyle::MiddleClick => self.middle_down,
+            ChordStyle::LeftClick | ChordStyle::Disabled => false,
+        }
+    }
+
+    fn mouse_pressed(&mut self, game: &mut Game, button: MouseButton, x: i32, y: i32) {
+        match button {
+            MouseButton::Left => self.mouse_down = true,
+            MouseButton::Middle => self.middle_down = true,
+            MouseButton::Right => {
+                self.right_down = true;
+                if !self.chord_held() {
+                    if let Some((x, y)) = self.renderer.tile_at(game, x, y) {
+                        // Clicks after the game is over are ignored.
+                        let _ = game.flag_tile(x, y);
                     }
                 }
+            }
+            _ => {}
+        }
+        self.update_preview(game, x, y);
+    }
+
+    fn mouse_released(&mut self, game: &mut Game, button: MouseButton, x: i32, y: i32) {
+        let tile = self.renderer.tile_at(game, x, y);
+        if self.chord_held() {
+            if let Some((x, y)) = tile {
+                let _ = game.chord_tile(x, y);
+            }
+            // The other button of a two-button chord is released later and
+            // must not reveal anything.
+            self.chorded = true;
+        } else if button == MouseButton::Left && !self.chorded {
+            if let Some((x, y)) = tile {
+                if !game.get_field()[x][y].revealed {
+                    let _ = game.reveal_tile(x, y);
+                } else if self.chord_style == ChordStyle::LeftClick {
+                    let _ = game.chord_tile(x, y);
+                }
+            }
+        }
+
+        match button {
+            MouseButton::Left => self.mouse_down = false,
+            MouseButton::Middle => self.middle_down = false,
+            MouseButton::Right => self.right_down = false,
+            _ => {}
+        }
+        if !self.mouse_down && !self.middle_down && !self.right_down {
+            self.chorded = false;
+        }
+        self.update_preview(game, x, y);
+    }
+
+    /// Presses the tile under the mouse, or the neighbours of a number while
+    /// chording.
+    fn update_preview(&mut self, game: &mut Game, x: i32, y: i32) {
+        let tile = self.renderer.tile_at(game, x, y);
+        let preview = if self.chord_held() {
+            tile
+        } else if self.mouse_down && !self.chorded {
+            let revealed = tile.is_some_and(|(x, y)| game.get_field()[x][y].revealed);
+            if revealed && self.chord_style != ChordStyle::LeftClick {
+                None
+            } else {
+                tile
+            }
+        } else {
+            None
+        };
+        game.set_preview(preview);
+    }
+
+    pub fn handle_replay_events(&mut self, playback: &mut Playback) -> bool {
+        let events: Vec<Event> = self.event_pump.poll_iter().collect();
+        for event in events {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => return true,
+                Event::KeyDown {
+                    keycode: Some(Keycode::Plus | Keycode::Equals | Keycode::KpPlus),
+                    ..
+                } => playback.speed = (playback.speed * 2.0).min(replay::MAX_SPEED),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Minus | Keycode::KpMinus),
+                    ..
+                } => playback.speed = (playback.speed / 2.0).max(replay::MIN_SPEED),
+                Event::KeyDown {
+                    keycode: Some(Keycode::Space),
+                    ..
+                } => playback.paused = !playback.paused,
                 _ => {}
             }
         }
+
+        let mut status = format!("Replay at {}x", playback.speed);
+        if playback.paused {
+            status += ", paused";
+        } else if playback.finished() {
+            status += ", finished";
+        }
+        self.playback = Some(status);
         false
     }
 }
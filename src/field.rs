@@ -1,29 +1,238 @@
-use rand::Rng;
+use crate::probability::mine_probabilities;
+use crate::replay::{Action, Input, Replay};
+use crate::save::{self, SavedGame};
+use crate::solver;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::fmt;
 use std::time::{Duration, Instant};
 
+/// Measures play time. Unlike a bare `Instant` it can be resumed from a
+/// previously elapsed duration, e.g. after loading a saved game.
+#[derive(Clone, Copy)]
+pub struct Clock {
+    started: Instant,
+    offset: Duration,
+}
+
+impl Clock {
+    pub fn start() -> Clock {
+        Clock::resume(Duration::ZERO)
+    }
+
+    pub fn resume(elapsed: Duration) -> Clock {
+        Clock {
+            started: Instant::now(),
+            offset: elapsed,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.offset + self.started.elapsed()
+    }
+}
+
+#[derive(Clone)]
 pub enum GameState {
-    Playing(Option<Instant>),
+    Playing(Option<Clock>),
     Victory(Duration),
     Boom(Duration),
 }
 
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub enum TileContent {
     Empty(i32),
     Bomb,
 }
 
-// #[derive(Copy, Clone)]
+#[derive(Clone, PartialEq, Eq, Debug)]
 pub struct Tile {
     pub content: TileContent,
     pub revealed: bool,
     pub flagged: bool,
+    /// Marked with a question mark. Unlike flags, these do not count
+    /// towards the mines remaining and do not block reveals.
+    pub questioned: bool,
 }
 
+#[derive(Clone)]
 enum PreviewState {
     NoPreview,
     Preview(usize, usize),
 }
 
+/// What the first click is protected from. Mines are moved or the board is
+/// regenerated when the first tile is revealed.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FirstClickPolicy {
+    /// The first click can hit a mine.
+    Unprotected,
+    /// The clicked tile has no mine.
+    SafeCell,
+    /// The clicked tile and its neighbours have no mines, so it opens an area.
+    Opening,
+    /// An opening on a board that can be cleared without guessing.
+    NoGuess,
+}
+
+impl FirstClickPolicy {
+    pub const ALL: [FirstClickPolicy; 4] = [
+        FirstClickPolicy::Unprotected,
+        FirstClickPolicy::SafeCell,
+        FirstClickPolicy::Opening,
+        FirstClickPolicy::NoGuess,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FirstClickPolicy::Unprotected => "none",
+            FirstClickPolicy::SafeCell => "safe",
+            FirstClickPolicy::Opening => "opening",
+            FirstClickPolicy::NoGuess => "no-guess",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<FirstClickPolicy> {
+        FirstClickPolicy::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// The most mines a field can hold while leaving room for the tiles this
+    /// policy keeps clear. 0 for fields with more than [`MAX_TILES`] tiles.
+    pub fn max_mines(self, w: usize, h: usize) -> i32 {
+        let clear = match self {
+            FirstClickPolicy::Unprotected => 0,
+            FirstClickPolicy::SafeCell => 1,
+            FirstClickPolicy::Opening | FirstClickPolicy::NoGuess => w.min(3) * h.min(3),
+        };
+        tile_count(w, h)
+            .and_then(|tiles| i32::try_from(tiles.saturating_sub(clear)).ok())
+            .unwrap_or(0)
+    }
+}
+
+/// Most tiles a field can have.
+pub const MAX_TILES: usize = 1 << 20;
+
+/// Number of tiles of a `w` by `h` field, or `None` if it is larger than
+/// [`MAX_TILES`].
+pub(crate) fn tile_count(w: usize, h: usize) -> Option<usize> {
+    w.checked_mul(h).filter(|&tiles| tiles <= MAX_TILES)
+}
+
+const MAX_GENERATION_ATTEMPTS: u32 = 1000;
+/// Tiles the solver may look at while searching for a no-guess board, summed
+/// over all attempts. Bounds the search on large boards, where a single
+/// attempt is slow, without making the result depend on the machine.
+const MAX_GENERATION_WORK: usize = 20_000_000;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum GameError {
+    /// The coordinates are outside the board.
+    InvalidCoordinate { x: usize, y: usize },
+    /// The game is already won or lost.
+    GameOver,
+    /// The board size or mine count cannot make a game.
+    InvalidConfiguration {
+        w: usize,
+        h: usize,
+        mine_count: i32,
+        max_mines: i32,
+    },
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::InvalidCoordinate { x, y } => {
+                write!(f, "Tile {} {} is outside the board!", x, y)
+            }
+            GameError::GameOver => write!(f, "The game is already over!"),
+            GameError::InvalidConfiguration { w, h, .. } if *w == 0 || *h == 0 => {
+                write!(f, "The field must be at least 1x1, not {}x{}!", w, h)
+            }
+            GameError::InvalidConfiguration { w, h, .. } if tile_count(*w, *h).is_none() => {
+                write!(
+                    f,
+                    "The field can have at most {} tiles, not {}x{}!",
+                    MAX_TILES, w, h
+                )
+            }
+            GameError::InvalidConfiguration {
+                w,
+                h,
+                mine_count,
+                max_mines,
+            } => write!(
+                f,
+                "A {}x{} field holds 0 to {} mines with this first click policy, not {}!",
+                w, h, max_mines, mine_count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GameError {}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Hint {
+    pub x: usize,
+    pub y: usize,
+    /// 0 when the tile is provably safe.
+    pub mine_probability: f64,
+}
+
+/// Clicks the player made: reveals, flags and chords.
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct Clicks {
+    pub left: u32,
+    pub right: u32,
+    pub chord: u32,
+}
+
+impl Clicks {
+    pub fn total(&self) -> u32 {
+        self.left + self.right + self.chord
+    }
+}
+
+/// Competitive metrics of a won game.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Metrics {
+    pub three_bv: u32,
+    pub three_bv_per_second: f64,
+    pub clicks: Clicks,
+    /// 3BV per click in percent.
+    pub efficiency: f64,
+}
+
+/// The game before an action, kept until it is known what the action changed.
+struct Snapshot {
+    field: Vec<Vec<Tile>>,
+    counters: Counters,
+}
+
+/// Everything an action changes besides the tiles.
+#[derive(Clone)]
+struct Counters {
+    state: GameState,
+    flag_count: i32,
+    revealed_count: i32,
+    rng: ChaCha8Rng,
+    generation_attempts: u32,
+    guess_free: bool,
+}
+
+/// The tiles an action changed, with their values from before it, and the
+/// counters. Applying it swaps them with the game's, which gives the change
+/// that reverts it again.
+#[derive(Clone)]
+struct Change {
+    tiles: Vec<(usize, usize, Tile)>,
+    counters: Counters,
+}
+
+#[derive(Clone)]
 pub struct Game {
     w: usize,
     h: usize,
@@ -33,13 +242,75 @@ pub struct Game {
     flag_count: i32,
     revealed_count: i32,
     preview: PreviewState,
+    seed: u64,
+    rng: ChaCha8Rng,
+    first_click: FirstClickPolicy,
+    generation_attempts: u32,
+    guess_free: bool,
+    question_marks: bool,
+    hints_used: u32,
+    clicks: Clicks,
+    undo_stack: Vec<Change>,
+    redo_stack: Vec<Change>,
+    assisted: bool,
+    recording_clock: Clock,
+    recording_start: String,
+    inputs: Vec<Input>,
 }
 
 impl Game {
-    pub fn new(w: usize, h: usize, mine_count: i32) -> Game {
-        let field = Game::generate_field(w, h, mine_count);
+    pub fn new(w: usize, h: usize, mine_count: i32) -> Result<Game, GameError> {
+        Game::with_seed(w, h, mine_count, rand::random())
+    }
+
+    /// Creates a game whose board, including the mines relocated by the
+    /// first click, is fully determined by `seed`. The first click opens an
+    /// area.
+    pub fn with_seed(w: usize, h: usize, mine_count: i32, seed: u64) -> Result<Game, GameError> {
+        Game::with_policy(w, h, mine_count, seed, FirstClickPolicy::Opening)
+    }
+
+    pub fn with_policy(
+        w: usize,
+        h: usize,
+        mine_count: i32,
+        seed: u64,
+        policy: FirstClickPolicy,
+    ) -> Result<Game, GameError> {
+        Game::check_configuration(w, h, mine_count, policy)?;
+        let mut game = Game::build(w, h, mine_count, seed);
+        game.set_first_click_policy(policy)?;
+        Ok(game)
+    }
 
-        Game {
+    fn check_configuration(
+        w: usize,
+        h: usize,
+        mine_count: i32,
+        policy: FirstClickPolicy,
+    ) -> Result<(), GameError> {
+        let max_mines = policy.max_mines(w, h);
+        if w == 0
+            || h == 0
+            || tile_count(w, h).is_none()
+            || mine_count < 0
+            || mine_count > max_mines
+        {
+            return Err(GameError::InvalidConfiguration {
+                w,
+                h,
+                mine_count,
+                max_mines,
+            });
+        }
+        Ok(())
+    }
+
+    fn build(w: usize, h: usize, mine_count: i32, seed: u64) -> Game {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let field = Game::generate_field(w, h, mine_count, &mut rng);
+
+        let mut game = Game {
             w,
             h,
             mine_count,
@@ -48,19 +319,138 @@ impl Game {
             flag_count: 0,
             revealed_count: 0,
             preview: PreviewState::NoPreview,
+            seed,
+            rng,
+            first_click: FirstClickPolicy::Opening,
+            generation_attempts: 0,
+            guess_free: false,
+            question_marks: false,
+            hints_used: 0,
+            clicks: Clicks::default(),
+            undo_stack: vec![],
+            redo_stack: vec![],
+            assisted: false,
+            recording_clock: Clock::start(),
+            recording_start: String::new(),
+            inputs: vec![],
+        };
+        game.start_recording(Some(seed));
+        game
+    }
+
+    /// Fails if the saved settings could not have made a game, e.g. with
+    /// more mines than the first click policy leaves room for.
+    pub(crate) fn from_saved(saved: SavedGame) -> Result<Game, GameError> {
+        let (w, h) = (saved.field.len(), saved.field[0].len());
+        Game::check_configuration(w, h, saved.mine_count, saved.first_click)?;
+        let mut field = saved.field;
+        for x in 0..w {
+            for y in 0..h {
+                if let TileContent::Empty(_) = field[x][y].content {
+                    let mut c = 0;
+                    Game::do_for_neightbors(
+                        x,
+                        y,
+                        |x, y| {
+                            if let TileContent::Bomb = field[x][y].content {
+                                c += 1;
+                            }
+                        },
+                        w,
+                        h,
+                    );
+                    field[x][y].content = TileContent::Empty(c);
+                }
+            }
+        }
+
+        let tiles = field.iter().flatten();
+        let flag_count = tiles.clone().filter(|t| t.flagged).count() as i32;
+        let revealed_count = tiles.filter(|t| t.revealed && !t.flagged).count() as i32;
+
+        let mut game = Game::build(w, h, saved.mine_count, saved.seed);
+        game.field = field;
+        game.state = saved.state;
+        game.flag_count = flag_count;
+        game.revealed_count = revealed_count;
+        game.first_click = saved.first_click;
+        game.question_marks = saved.question_marks;
+        game.generation_attempts = saved.generation_attempts;
+        game.guess_free = saved.guess_free;
+        game.hints_used = saved.hints_used;
+        game.clicks = saved.clicks;
+        game.assisted = saved.assisted;
+        game.start_recording(None);
+        Ok(game)
+    }
+
+    pub(crate) fn to_saved(&self) -> SavedGame {
+        SavedGame {
+            mine_count: self.mine_count,
+            seed: self.seed,
+            first_click: self.first_click,
+            question_marks: self.question_marks,
+            generation_attempts: self.generation_attempts,
+            guess_free: self.guess_free,
+            hints_used: self.hints_used,
+            clicks: self.clicks,
+            assisted: self.assisted,
+            state: self.state.clone(),
+            field: self.field.clone(),
         }
     }
 
     pub fn new_game(&mut self) {
-        let field = Game::generate_field(self.w, self.h, self.mine_count);
+        self.new_game_with_seed(rand::random());
+    }
+
+    pub fn new_game_with_seed(&mut self, seed: u64) {
+        self.seed = seed;
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        let field = Game::generate_field(self.w, self.h, self.mine_count, &mut self.rng);
         self.field = field;
         self.state = GameState::Playing(None);
         self.flag_count = 0;
         self.revealed_count = 0;
         self.preview = PreviewState::NoPreview;
+        self.generation_attempts = 0;
+        self.guess_free = false;
+        self.hints_used = 0;
+        self.clicks = Clicks::default();
+        self.undo_stack.clear();
+        self.redo_stack.clear();
+        self.assisted = false;
+        self.start_recording(Some(seed));
+    }
+
+    // Recording starts before the first click or from a loaded file, and
+    // neither can hold a tile the save format has no symbol for.
+    fn start_position(&self) -> String {
+        save::to_string(self).expect("Cannot save the board a replay starts from!")
+    }
+
+    fn start_recording(&mut self, new_game: Option<u64>) {
+        self.recording_clock = Clock::start();
+        self.recording_start = self.start_position();
+        self.inputs = vec![];
+        if let Some(seed) = new_game {
+            self.record(Action::NewGame(seed));
+        }
+    }
+
+    fn record(&mut self, action: Action) {
+        self.inputs.push(Input {
+            time: self.recording_clock.elapsed(),
+            action,
+        });
     }
 
-    fn generate_field(w: usize, h: usize, mine_count: i32) -> Vec<Vec<Tile>> {
+    /// Every input this game received since it was set up or loaded.
+    pub fn replay(&self) -> Replay {
+        Replay::new(self.recording_start.clone(), self.inputs.clone())
+    }
+
+    fn generate_field(w: usize, h: usize, mine_count: i32, rng: &mut ChaCha8Rng) -> Vec<Vec<Tile>> {
         let mut field: Vec<Vec<Tile>> = vec![];
         for _ in 0..w {
             let mut row = vec![];
@@ -69,17 +459,27 @@ impl Game {
                     content: TileContent::Empty(0),
                     revealed: false,
                     flagged: false,
+                    questioned: false,
                 };
                 row.push(new_tile);
             }
             field.push(row);
         }
 
-        let mut c = 0;
-        let mut rng = rand::thread_rng();
+        if mine_count as usize * 2 > w * h {
+            // Picking random tiles until a free one comes up slows down as
+            // the field fills, so dense fields take a random subset instead.
+            let mut tiles: Vec<usize> = (0..w * h).collect();
+            let (mines, _) = tiles.partial_shuffle(rng, mine_count as usize);
+            for &i in mines.iter() {
+                Game::place_mine(&mut field, i / h, i % h, w, h);
+            }
+            return field;
+        }
 
+        let mut c = 0;
         while c < mine_count {
-            let (x, y) = (rng.gen_range(0..w) as usize, rng.gen_range(0..h) as usize);
+            let (x, y) = (rng.gen_range(0..w), rng.gen_range(0..h));
             let tile = &mut field[x][y];
             if let TileContent::Bomb = tile.content {
                 continue;
@@ -92,7 +492,7 @@ impl Game {
         field
     }
 
-    fn do_for_neightbors<F: FnMut(usize, usize)>(
+    pub(crate) fn do_for_neightbors<F: FnMut(usize, usize)>(
         x: usize,
         y: usize,
         mut func: F,
@@ -133,10 +533,7 @@ impl Game {
     fn add_empty(tile: &mut Tile, add: i32) {
         if let TileContent::Empty(ref mut x) = tile.content {
             *x += add;
-
-            if *x < 0 || *x > 8 {
-                panic!("Invalid value for tile neighbor!");
-            }
+            debug_assert!((0..=8).contains(x), "Invalid value for tile neighbor!");
         }
     }
 
@@ -144,7 +541,7 @@ impl Game {
         let tile = &mut field[x][y];
 
         if let TileContent::Bomb = tile.content {
-            panic!("Cannot place bomb - already bomb here!");
+            return false;
         }
 
         tile.content = TileContent::Bomb;
@@ -152,7 +549,8 @@ impl Game {
         true
     }
 
-    fn move_mine(&mut self, x: usize, y: usize) {
+    /// Moves the mine at `x`, `y` to a random free tile outside `keep_clear`.
+    fn move_mine(&mut self, x: usize, y: usize, keep_clear: &[(usize, usize)]) {
         Game::do_for_neightbors(
             x,
             y,
@@ -161,22 +559,26 @@ impl Game {
             self.h as usize,
         );
 
-        let mut rng = rand::thread_rng();
-        loop {
-            let (new_x, new_y) = (
-                rng.gen_range(0..self.w) as usize,
-                rng.gen_range(0..self.h) as usize,
-            );
-            if new_x == x && new_y == y {
-                continue;
-            }
-            if let TileContent::Bomb = self.field[new_x][new_y].content {
-                continue;
+        let free = |field: &Vec<Vec<Tile>>, x: usize, y: usize| {
+            !matches!(field[x][y].content, TileContent::Bomb) && !keep_clear.contains(&(x, y))
+        };
+        let candidates: Vec<(usize, usize)> = (0..self.w)
+            .flat_map(|x| (0..self.h).map(move |y| (x, y)))
+            .filter(|&(x, y)| free(&self.field, x, y))
+            .collect();
+        // Sparse fields sample tiles directly, like the field generation
+        // does, so seeds give the same boards.
+        let (new_x, new_y) = if candidates.len() * 2 >= self.w * self.h {
+            loop {
+                let (new_x, new_y) = (self.rng.gen_range(0..self.w), self.rng.gen_range(0..self.h));
+                if free(&self.field, new_x, new_y) {
+                    break (new_x, new_y);
+                }
             }
-
-            Game::place_mine(&mut self.field, new_x, new_y, self.w, self.h);
-            break;
-        }
+        } else {
+            candidates[self.rng.gen_range(0..candidates.len())]
+        };
+        Game::place_mine(&mut self.field, new_x, new_y, self.w, self.h);
 
         let mut c = 0;
         Game::do_for_neightbors(
@@ -193,6 +595,93 @@ impl Game {
         self.field[x][y].content = TileContent::Empty(c);
     }
 
+    fn clear_around(&mut self, x: usize, y: usize) {
+        let (w, h) = self.size();
+        let mut zone = vec![];
+        Game::do_for_neightbors(x, y, |x, y| zone.push((x, y)), w, h);
+        zone.push((x, y));
+        for &(x, y) in &zone {
+            if matches!(self.field[x][y].content, TileContent::Bomb) {
+                self.move_mine(x, y, &zone);
+            }
+        }
+    }
+
+    /// Moves the mine off the clicked tile only.
+    fn clear_tile(&mut self, x: usize, y: usize) {
+        if matches!(self.field[x][y].content, TileContent::Bomb) {
+            self.move_mine(x, y, &[(x, y)]);
+        }
+    }
+
+    fn generate_from_click(&mut self, x: usize, y: usize) {
+        self.generation_attempts = 1;
+        match self.first_click {
+            FirstClickPolicy::Unprotected => return,
+            FirstClickPolicy::SafeCell => return self.clear_tile(x, y),
+            FirstClickPolicy::Opening => return self.clear_around(x, y),
+            FirstClickPolicy::NoGuess => self.clear_around(x, y),
+        }
+
+        // Marks placed before the first click stay on the new boards.
+        let marks: Vec<Vec<(bool, bool)>> = self
+            .field
+            .iter()
+            .map(|column| column.iter().map(|t| (t.flagged, t.questioned)).collect())
+            .collect();
+        let mut work = MAX_GENERATION_WORK;
+        loop {
+            self.guess_free = self.solvable_from(x, y, &mut work);
+            if self.guess_free || self.generation_attempts == MAX_GENERATION_ATTEMPTS || work == 0 {
+                break;
+            }
+            self.field = Game::generate_field(self.w, self.h, self.mine_count, &mut self.rng);
+            for (column, marks) in self.field.iter_mut().zip(&marks) {
+                for (tile, &(flagged, questioned)) in column.iter_mut().zip(marks) {
+                    tile.flagged = flagged;
+                    tile.questioned = questioned;
+                }
+            }
+            self.clear_around(x, y);
+            self.generation_attempts += 1;
+        }
+    }
+
+    /// Plays the board with the solver from a click on `x`, `y`. Every
+    /// solver step uses up the board's tile count from `work`, and the board
+    /// counts as unsolvable once it runs out.
+    fn solvable_from(&self, x: usize, y: usize, work: &mut usize) -> bool {
+        let mut sim = self.clone();
+        sim.first_click = FirstClickPolicy::Opening;
+        sim.field.iter_mut().flatten().for_each(|t| {
+            t.flagged = false;
+            t.questioned = false;
+        });
+        sim.flag_count = 0;
+        sim.game_start();
+        sim.reveal(x, y);
+
+        while matches!(sim.state, GameState::Playing(_)) {
+            if *work < self.w * self.h {
+                *work = 0;
+                return false;
+            }
+            *work -= self.w * self.h;
+            let deductions = solver::deduce(&sim);
+            if deductions.is_empty() {
+                return false;
+            }
+            for d in deductions {
+                if d.mine {
+                    sim.flag(d.x, d.y);
+                } else {
+                    sim.reveal(d.x, d.y);
+                }
+            }
+        }
+        matches!(sim.state, GameState::Victory(_))
+    }
+
     pub fn width(&self) -> usize {
         self.w
     }
@@ -204,18 +693,56 @@ impl Game {
         (self.w, self.h)
     }
 
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn first_click_policy(&self) -> FirstClickPolicy {
+        self.first_click
+    }
+
+    /// Takes effect on the next first click, which is when the final board
+    /// is generated. Fails if the policy needs more tiles kept clear than the
+    /// mine count leaves free.
+    pub fn set_first_click_policy(&mut self, policy: FirstClickPolicy) -> Result<(), GameError> {
+        Game::check_configuration(self.w, self.h, self.mine_count, policy)?;
+        self.first_click = policy;
+        if matches!(self.state, GameState::Playing(None)) {
+            self.recording_start = self.start_position();
+        }
+        Ok(())
+    }
+
+    /// Number of boards generated on the first click, 0 before it.
+    pub fn generation_attempts(&self) -> u32 {
+        self.generation_attempts
+    }
+
+    /// Whether the board was proven to be clearable without guessing. Only
+    /// checked for [`FirstClickPolicy::NoGuess`], which gives up and keeps
+    /// the last board when no such board turns up within its limits.
+    pub fn guess_free(&self) -> bool {
+        self.guess_free
+    }
+
+    pub fn mine_count(&self) -> i32 {
+        self.mine_count
+    }
+
     pub fn mines_remaining(&self) -> i32 {
         std::cmp::max(0, self.mine_count - self.flag_count)
     }
 
     pub fn time(&self) -> i32 {
+        self.elapsed().as_secs().min(999) as i32
+    }
+
+    pub fn elapsed(&self) -> Duration {
         match self.state {
-            GameState::Boom(t) => t.as_secs().min(999) as i32,
-            GameState::Victory(t) => t.as_secs().min(999) as i32,
-            GameState::Playing(Some(t)) => {
-                Instant::now().duration_since(t).as_secs().min(999) as i32
-            }
-            GameState::Playing(None) => 0,
+            GameState::Boom(t) => t,
+            GameState::Victory(t) => t,
+            GameState::Playing(Some(clock)) => clock.elapsed(),
+            GameState::Playing(None) => Duration::ZERO,
         }
     }
 
@@ -227,7 +754,7 @@ impl Game {
     }
 
     pub fn preview_at(&self, x: usize, y: usize) -> bool {
-        if !matches!(self.state, GameState::Playing(_)) {
+        if !matches!(self.state, GameState::Playing(_)) || self.check_coordinate(x, y).is_err() {
             return false;
         }
 
@@ -257,11 +784,13 @@ impl Game {
         false
     }
 
+    /// Shows which tiles a click at `pos` would uncover. Positions outside
+    /// the board clear the preview.
     pub fn set_preview(&mut self, pos: Option<(usize, usize)>) {
         if !matches!(self.state, GameState::Playing(_)) {
             return;
         }
-        match pos {
+        match pos.filter(|&(x, y)| self.check_coordinate(x, y).is_ok()) {
             None => self.preview = PreviewState::NoPreview,
             Some((x, y)) => {
                 let tile = &self.field[x][y];
@@ -272,6 +801,115 @@ impl Game {
         }
     }
 
+    pub fn hints_used(&self) -> u32 {
+        self.hints_used
+    }
+
+    /// Suggests a provably safe tile, or the one least likely to be a mine if
+    /// there is none, and counts it as a used hint. There is no hint before
+    /// the first click, when the mines are not final yet.
+    pub fn hint(&mut self) -> Option<Hint> {
+        if !matches!(self.state, GameState::Playing(Some(_))) {
+            return None;
+        }
+
+        let hint = match solver::deduce(self).into_iter().find(|d| !d.mine) {
+            Some(d) => Hint {
+                x: d.x,
+                y: d.y,
+                mine_probability: 0.0,
+            },
+            None => {
+                let probabilities = mine_probabilities(self)?;
+                let mut best: Option<Hint> = None;
+                for (x, column) in probabilities.iter().enumerate() {
+                    for (y, p) in column.iter().enumerate() {
+                        if let Some(p) = *p {
+                            let better = match best {
+                                Some(b) => p < b.mine_probability,
+                                None => true,
+                            };
+                            if better {
+                                best = Some(Hint {
+                                    x,
+                                    y,
+                                    mine_probability: p,
+                                });
+                            }
+                        }
+                    }
+                }
+                best?
+            }
+        };
+
+        self.record(Action::Hint);
+        self.hints_used += 1;
+        Some(hint)
+    }
+
+    pub fn clicks(&self) -> Clicks {
+        self.clicks
+    }
+
+    /// The board's 3BV: the minimum number of left clicks needed to clear it,
+    /// one per opening plus one per number not next to an opening. The mines
+    /// only settle on the first click, so the value is final after it.
+    pub fn three_bv(&self) -> u32 {
+        let (w, h) = self.size();
+        let mut cleared = vec![vec![false; h]; w];
+        let mut count = 0;
+
+        for x in 0..w {
+            for y in 0..h {
+                if cleared[x][y] || !matches!(self.field[x][y].content, TileContent::Empty(0)) {
+                    continue;
+                }
+                count += 1;
+                cleared[x][y] = true;
+                let mut opening = vec![(x, y)];
+                while let Some((x, y)) = opening.pop() {
+                    Game::do_for_neightbors(
+                        x,
+                        y,
+                        |x, y| {
+                            if !cleared[x][y] {
+                                cleared[x][y] = true;
+                                if let TileContent::Empty(0) = self.field[x][y].content {
+                                    opening.push((x, y));
+                                }
+                            }
+                        },
+                        w,
+                        h,
+                    );
+                }
+            }
+        }
+
+        let numbers = self.field.iter().flatten().zip(cleared.iter().flatten());
+        count
+            + numbers
+                .filter(|(t, &c)| !c && matches!(t.content, TileContent::Empty(_)))
+                .count() as u32
+    }
+
+    /// 3BV, 3BV/s, clicks and efficiency once the game is won.
+    pub fn metrics(&self) -> Option<Metrics> {
+        let time = match self.state {
+            GameState::Victory(t) => t,
+            _ => return None,
+        };
+        let three_bv = self.three_bv();
+        let clicks = self.clicks;
+        Some(Metrics {
+            three_bv,
+            three_bv_per_second: three_bv as f64 / time.as_secs_f64().max(0.001),
+            clicks,
+            efficiency: three_bv as f64 / clicks.total().max(1) as f64 * 100.0,
+        })
+    }
+
     pub fn state(&self) -> &GameState {
         &self.state
     }
@@ -280,21 +918,213 @@ impl Game {
         &self.field
     }
 
-    pub fn flag_tile(&mut self, x: usize, y: usize) {
+    /// The next hidden tile without a flag after `(x, y)` in reading order,
+    /// or before it when going backwards. `None` if there is no other one.
+    pub fn next_unrevealed(&self, x: usize, y: usize, forward: bool) -> Option<(usize, usize)> {
+        let n = self.w * self.h;
+        let start = y * self.w + x;
+        (1..n)
+            .map(|i| {
+                if forward {
+                    (start + i) % n
+                } else {
+                    (start + n - i) % n
+                }
+            })
+            .map(|index| (index % self.w, index / self.w))
+            .find(|&(x, y)| !self.field[x][y].revealed && !self.field[x][y].flagged)
+    }
+
+    fn check_coordinate(&self, x: usize, y: usize) -> Result<(), GameError> {
+        if x >= self.w || y >= self.h {
+            return Err(GameError::InvalidCoordinate { x, y });
+        }
+        Ok(())
+    }
+
+    fn check_playing(&self) -> Result<(), GameError> {
+        match self.state {
+            GameState::Playing(_) => Ok(()),
+            _ => Err(GameError::GameOver),
+        }
+    }
+
+    pub fn question_marks(&self) -> bool {
+        self.question_marks
+    }
+
+    /// Whether marking a tile cycles through flag and question mark instead
+    /// of only toggling the flag. Question marks already placed stay when
+    /// this is turned off.
+    pub fn set_question_marks(&mut self, enabled: bool) {
+        self.question_marks = enabled;
+        match self.state {
+            GameState::Playing(None) => self.recording_start = self.start_position(),
+            GameState::Playing(Some(_)) => self.record(Action::QuestionMarks(enabled)),
+            _ => {}
+        }
+    }
+
+    /// Cycles the mark on a hidden tile: none, flag, then question mark if
+    /// enabled. Revealed tiles are left alone.
+    pub fn flag_tile(&mut self, x: usize, y: usize) -> Result<(), GameError> {
+        self.check_coordinate(x, y)?;
+        self.check_playing()?;
+        self.record(Action::Flag(x, y));
+        self.clicks.right += 1;
+        let before = self.snapshot();
+        self.flag(x, y);
+        if before.field[x][y] != self.field[x][y] {
+            self.remember(before);
+        }
+        Ok(())
+    }
+
+    /// Reveals a hidden tile. On a revealed number this chords instead, see
+    /// [`Game::chord_tile`]. Flagged tiles are left alone.
+    pub fn reveal_tile(&mut self, x: usize, y: usize) -> Result<(), GameError> {
+        self.check_coordinate(x, y)?;
+        if self.field[x][y].revealed {
+            return self.chord_tile(x, y);
+        }
+        self.check_playing()?;
+        self.record(Action::Reveal(x, y));
+        self.clicks.left += 1;
+        let before = self.snapshot();
+        self.reveal(x, y);
+        if self.revealed_count != before.counters.revealed_count {
+            self.remember(before);
+        }
+        Ok(())
+    }
+
+    /// Reveals all unflagged neighbours of a revealed number if it has as
+    /// many flags around it as its value. Hidden tiles are left alone.
+    pub fn chord_tile(&mut self, x: usize, y: usize) -> Result<(), GameError> {
+        self.check_coordinate(x, y)?;
+        self.check_playing()?;
+        if !self.field[x][y].revealed {
+            return Ok(());
+        }
+        self.record(Action::Chord(x, y));
+        self.clicks.chord += 1;
+        let before = self.snapshot();
+        self.reveal(x, y);
+        if self.revealed_count != before.counters.revealed_count {
+            self.remember(before);
+        }
+        Ok(())
+    }
+
+    /// Steps back one reveal or flag action, including one that hit a mine.
+    /// Marks the game as assisted.
+    pub fn undo(&mut self) -> bool {
+        self.record(Action::Undo);
+        match self.undo_stack.pop() {
+            Some(change) => {
+                let redo = self.apply(change);
+                self.redo_stack.push(redo);
+                self.assisted = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn redo(&mut self) -> bool {
+        self.record(Action::Redo);
+        match self.redo_stack.pop() {
+            Some(change) => {
+                let undo = self.apply(change);
+                self.undo_stack.push(undo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether undo was used in this game.
+    pub fn assisted(&self) -> bool {
+        self.assisted
+    }
+
+    fn counters(&self) -> Counters {
+        Counters {
+            state: self.state.clone(),
+            flag_count: self.flag_count,
+            revealed_count: self.revealed_count,
+            rng: self.rng.clone(),
+            generation_attempts: self.generation_attempts,
+            guess_free: self.guess_free,
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            field: self.field.clone(),
+            counters: self.counters(),
+        }
+    }
+
+    /// Keeps the tiles that changed since `before` for undo.
+    fn remember(&mut self, before: Snapshot) {
+        let mut tiles = vec![];
+        for (x, (old, new)) in before.field.into_iter().zip(&self.field).enumerate() {
+            for (y, (old, new)) in old.into_iter().zip(new).enumerate() {
+                if old != *new {
+                    tiles.push((x, y, old));
+                }
+            }
+        }
+        self.undo_stack.push(Change {
+            tiles,
+            counters: before.counters,
+        });
+        self.redo_stack.clear();
+    }
+
+    fn apply(&mut self, mut change: Change) -> Change {
+        for (x, y, tile) in change.tiles.iter_mut() {
+            std::mem::swap(tile, &mut self.field[*x][*y]);
+        }
+        let counters = self.counters();
+        self.state = change.counters.state;
+        self.flag_count = change.counters.flag_count;
+        self.revealed_count = change.counters.revealed_count;
+        self.rng = change.counters.rng;
+        self.generation_attempts = change.counters.generation_attempts;
+        self.guess_free = change.counters.guess_free;
+        self.preview = PreviewState::NoPreview;
+        Change {
+            tiles: change.tiles,
+            counters,
+        }
+    }
+
+    fn flag(&mut self, x: usize, y: usize) {
         if !matches!(self.state, GameState::Playing(_)) {
             return;
         }
 
+        let question_marks = self.question_marks;
         let tile = &mut self.field[x][y];
 
         if tile.revealed {
             return;
         }
-        tile.flagged = !tile.flagged;
-        self.flag_count += if tile.flagged { 1 } else { -1 }
+        if tile.flagged {
+            tile.flagged = false;
+            tile.questioned = question_marks;
+            self.flag_count -= 1;
+        } else if tile.questioned {
+            tile.questioned = false;
+        } else {
+            tile.flagged = true;
+            self.flag_count += 1;
+        }
     }
 
-    pub fn reveal_tile(&mut self, x: usize, y: usize) {
+    fn reveal(&mut self, x: usize, y: usize) {
         if !matches!(self.state, GameState::Playing(_)) {
             return;
         }
@@ -305,21 +1135,7 @@ impl Game {
 
         if !tile.revealed {
             if matches!(self.state, GameState::Playing(None)) {
-                let (w, h) = self.size();
-                Game::do_for_neightbors(
-                    x,
-                    y,
-                    |x, y| {
-                        if matches!(self.field[x][y].content, TileContent::Bomb) {
-                            self.move_mine(x, y);
-                        }
-                    },
-                    w,
-                    h,
-                );
-                if matches!(self.field[x][y].content, TileContent::Bomb) {
-                    self.move_mine(x, y);
-                }
+                self.generate_from_click(x, y);
                 self.game_start();
             }
             self.reveal_rec(x, y);
@@ -351,8 +1167,6 @@ impl Game {
                         h,
                     );
                 }
-            } else {
-                panic!("Invalid tile content for revealing!");
             }
         }
 
@@ -364,6 +1178,7 @@ impl Game {
     fn reveal_rec(&mut self, x: usize, y: usize) {
         let tile = &mut self.field[x][y];
         tile.revealed = true;
+        tile.questioned = false;
         self.revealed_count += 1;
         match tile.content {
             TileContent::Bomb => self.game_boom(),
@@ -395,6 +1210,7 @@ impl Game {
                 if matches!(self.state, GameState::Victory(_)) {
                     if matches!(self.field[x][y].content, TileContent::Bomb) {
                         self.field[x][y].flagged = true;
+                        self.field[x][y].questioned = false;
                     }
                 }
                 if self.field[x][y].flagged {
@@ -408,30 +1224,98 @@ impl Game {
         if !matches!(self.state, GameState::Playing(Option::None)) {
             return;
         }
-        self.state = GameState::Playing(Some(Instant::now()));
+        self.state = GameState::Playing(Some(Clock::start()));
     }
 
+    // A chord can uncover several mines at once, so both of these can be
+    // reached after the game has already ended.
     fn game_boom(&mut self) {
-        match self.state {
-            GameState::Playing(t) => {
-                let now = Instant::now();
-                self.state = GameState::Boom(now.duration_since(t.unwrap_or(now)));
-                self.preview = PreviewState::NoPreview;
-                self.reveal_field();
-            }
-            _ => panic!("Cannot boom the game in current state!"),
+        if let GameState::Playing(_) = self.state {
+            self.state = GameState::Boom(self.elapsed());
+            self.preview = PreviewState::NoPreview;
+            self.reveal_field();
         }
     }
 
     fn game_yay(&mut self) {
-        match self.state {
-            GameState::Playing(t) => {
-                let now = Instant::now();
-                self.state = GameState::Victory(now.duration_since(t.unwrap_or(now)));
-                self.preview = PreviewState::NoPreview;
-                self.reveal_field();
+        if let GameState::Playing(_) = self.state {
+            self.state = GameState::Victory(self.elapsed());
+            self.preview = PreviewState::NoPreview;
+            self.reveal_field();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn undo_and_redo_walk_back_and_forth() {
+        let mut game = Game::with_seed(16, 16, 40, 7).unwrap();
+        let mut boards = vec![game.get_field().clone()];
+        game.flag_tile(0, 0).unwrap();
+        boards.push(game.get_field().clone());
+        for (x, y) in [(8, 8), (0, 15), (15, 0), (3, 12), (12, 3)] {
+            let tile = &game.get_field()[x][y];
+            if tile.revealed || tile.flagged || !matches!(game.state(), GameState::Playing(_)) {
+                continue;
             }
-            _ => panic!("Cannot win the game in current state!"),
+            game.reveal_tile(x, y).unwrap();
+            boards.push(game.get_field().clone());
         }
+        assert!(boards.len() > 3);
+
+        for board in boards.iter().rev().skip(1) {
+            assert!(game.undo());
+            assert_eq!(game.get_field(), board);
+        }
+        assert!(!game.undo());
+        for board in boards.iter().skip(1) {
+            assert!(game.redo());
+            assert_eq!(game.get_field(), board);
+        }
+        assert!(game.assisted());
+    }
+
+    #[test]
+    fn rejects_oversized_fields() {
+        for (w, h) in [(1 << 33, 1 << 33), (70000, 70000), (MAX_TILES + 1, 1)] {
+            assert!(matches!(
+                Game::new(w, h, 10),
+                Err(GameError::InvalidConfiguration { .. })
+            ));
+            assert!(matches!(
+                Game::with_policy(w, h, 0, 0, FirstClickPolicy::Unprotected),
+                Err(GameError::InvalidConfiguration { .. })
+            ));
+        }
+        assert!(Game::with_policy(1 << 10, 1 << 10, 10, 0, FirstClickPolicy::Unprotected).is_ok());
+    }
+
+    #[test]
+    fn same_seed_gives_same_board() {
+        for policy in FirstClickPolicy::ALL {
+            let mut a = Game::with_policy(30, 16, 99, 42, policy).unwrap();
+            let mut b = Game::with_policy(30, 16, 99, 42, policy).unwrap();
+            assert_eq!(a.get_field(), b.get_field());
+            // The first click moves mines or regenerates the board.
+            a.reveal_tile(10, 5).unwrap();
+            b.reveal_tile(10, 5).unwrap();
+            assert_eq!(a.get_field(), b.get_field(), "{}", policy.name());
+        }
+
+        let a = Game::with_seed(30, 16, 99, 1).unwrap();
+        let b = Game::with_seed(30, 16, 99, 2).unwrap();
+        assert_ne!(a.get_field(), b.get_field());
+    }
+
+    #[test]
+    fn three_bv_counts_openings_and_lone_numbers() {
+        // Two openings, plus the two 1s between the top mines that touch neither.
+        let text = "minesweeper-save 5\nsize 8 3\nmines 3\nseed 0\nfirst-click none\n\
+                    state new\nelapsed 0\nfield\n..*..*..\n........\n*.......\n";
+        let game = crate::save::from_str(text).unwrap();
+        assert_eq!(game.three_bv(), 4);
     }
 }
@@ -0,0 +1,33 @@
+//! The layout shared by every file the game reads and writes: saves, replays,
+//! scores, statistics and theme manifests.
+//!
+//! These files are UTF-8 text. The first line identifies the format and its
+//! version, separated by a space, e.g. `minesweeper-save 5`. Each format's
+//! module describes the lines that follow. Readers accept every version up to
+//! the one they write.
+
+use std::io::{Error, ErrorKind, Result};
+use std::ops::RangeInclusive;
+use std::str::Lines;
+
+/// Reads the first line and returns its version if it names the format
+/// `header` in one of `versions`. `kind` names the file in error messages,
+/// e.g. `save`.
+pub(crate) fn check_header(
+    lines: &mut Lines,
+    header: &str,
+    versions: RangeInclusive<u32>,
+    kind: &str,
+) -> Result<u32> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+    match lines.next().unwrap_or_default().split_once(' ') {
+        Some((name, version)) if name == header => match version.parse() {
+            Ok(v) if versions.contains(&v) => Ok(v),
+            _ => Err(invalid(format!(
+                "Unsupported {} version {}!",
+                kind, version
+            ))),
+        },
+        _ => Err(invalid(format!("Not a minesweeper {} file!", kind))),
+    }
+}
@@ -0,0 +1,26 @@
+//! Minesweeper rules engine.
+//!
+//! [`field::Game`] holds the complete game logic and does not depend on SDL,
+//! so it can be driven headlessly. The SDL frontend in [`draw`] is only built
+//! with the `sdl` feature, which is enabled by default, and the terminal
+//! frontend in [`tui`] with the `tui` feature.
+
+#[cfg(feature = "sdl")]
+pub mod draw;
+pub mod field;
+pub mod format;
+pub mod preset;
+pub mod probability;
+#[cfg(feature = "sdl")]
+pub mod render;
+pub mod replay;
+pub mod results;
+pub mod save;
+pub mod scores;
+pub mod solver;
+pub mod stats;
+pub mod theme;
+#[cfg(feature = "tui")]
+pub mod tui;
+
+pub use field::{Game, GameError, GameState, Tile, TileContent};
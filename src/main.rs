@@ -1,42 +1,208 @@
-extern crate sdl2;
-
 use std::time::{Duration, Instant};
 
-mod draw;
-mod field;
-use draw::Drawer;
-use field::Game;
+use minesweeper_rust::draw::{ChordStyle, Drawer, Scale};
+use minesweeper_rust::preset::{Preset, Settings};
+use minesweeper_rust::render::{self, Renderer, ThemeSource};
+use minesweeper_rust::replay::{self, Playback};
+use minesweeper_rust::save;
+use minesweeper_rust::scores;
+use minesweeper_rust::theme;
+use minesweeper_rust::Game;
+use std::path::{Path, PathBuf};
 
 const FPS: f64 = 60.0;
 
 fn main() {
-    let (mut w, mut h, mut mc) = (8, 8, 10);
-    let args: Vec<String> = std::env::args().collect();
-    if args.len() >= 4 {
-        w = args[1]
-            .parse()
-            .expect("Cannot parse first argument (field width)!");
-        h = args[2]
-            .parse()
-            .expect("Cannot parse first argument (field height)!");
-        mc = args[3]
-            .parse()
-            .expect("Cannot parse first argument (mine count)!");
-
-        if w < 8 || h < 8 || w > 200 || h > 200 || mc < 10 {
-            println!(
-                "Invalid parameters: width: {}, height: {}, mine count: {}!",
-                w, h, mc
-            );
-            println!("200 >= w,h >= 8, mine count >= 10");
+    let mut chord_style = ChordStyle::LeftClick;
+    let mut load = None;
+    let mut replay_file = None;
+    let mut speed = 1.0;
+    let mut screenshot = None;
+    let mut frame = None;
+    let mut assets = None;
+    let mut theme_file = None;
+    let mut scale = Scale::Fixed(1);
+    let mut args: Vec<String> = vec![];
+    let mut arg_iter = std::env::args().skip(1);
+    while let Some(arg) = arg_iter.next() {
+        match arg.as_str() {
+            "--chord" => {
+                let value = arg_iter.next().expect("Missing value for --chord!");
+                chord_style = ChordStyle::from_name(&value).unwrap_or_else(|| {
+                    println!("Unknown chord style '{}'!", value);
+                    println!("Styles: left, both, middle, off");
+                    std::process::exit(1);
+                });
+            }
+            "--load" => {
+                let value = arg_iter.next().expect("Missing value for --load!");
+                load = Some(PathBuf::from(value));
+            }
+            "--replay" => {
+                let value = arg_iter.next().expect("Missing value for --replay!");
+                replay_file = Some(PathBuf::from(value));
+            }
+            "--speed" => {
+                let value = arg_iter.next().expect("Missing value for --speed!");
+                speed = value
+                    .parse()
+                    .ok()
+                    .filter(|s| (replay::MIN_SPEED..=replay::MAX_SPEED).contains(s))
+                    .unwrap_or_else(|| {
+                        println!("Invalid replay speed '{}'!", value);
+                        println!("Speeds range from 0.0625 to 16.");
+                        std::process::exit(1);
+                    });
+            }
+            "--screenshot" => {
+                let value = arg_iter.next().expect("Missing value for --screenshot!");
+                screenshot = Some(PathBuf::from(value));
+            }
+            "--frame" => {
+                let value = arg_iter.next().expect("Missing value for --frame!");
+                frame = Some(value.parse().expect("Cannot parse replay frame!"));
+            }
+            "--assets" => {
+                let value = arg_iter.next().expect("Missing value for --assets!");
+                assets = Some(PathBuf::from(value));
+            }
+            "--theme" => {
+                let value = arg_iter.next().expect("Missing value for --theme!");
+                theme_file = Some(PathBuf::from(value));
+            }
+            "--scale" => {
+                let value = arg_iter.next().expect("Missing value for --scale!");
+                scale = Scale::from_name(&value).unwrap_or_else(|| {
+                    println!("Unknown scale '{}'!", value);
+                    println!("Scales: auto, 1, 2, 3, 4");
+                    std::process::exit(1);
+                });
+            }
+            "--scores" => {
+                print_scores(Path::new("minesweeper.scores"));
+                return;
+            }
+            _ => args.push(arg),
+        }
+    }
+
+    let settings = Settings::from_args(args).unwrap_or_else(|err| {
+        println!("{}", err);
+        std::process::exit(1);
+    });
+    let classic = ThemeSource::Classic(render::assets_dir(assets));
+    let theme = match theme_file {
+        Some(path) => ThemeSource::Manifest(path.canonicalize().unwrap_or(path)),
+        None => classic.clone(),
+    };
+    if let Some(path) = replay_file {
+        let replay = replay::load(&path).unwrap_or_else(|err| {
+            println!("Cannot load replay from {}! {}", path.display(), err);
             std::process::exit(1);
+        });
+        let mut game = replay.start();
+        if let Some(path) = screenshot {
+            let frame = frame.unwrap_or(replay.inputs().len());
+            for input in replay.inputs().iter().take(frame) {
+                // Inputs that fail were ignored while recording as well.
+                let _ = replay::apply(&mut game, input.action);
+            }
+            save_screenshot(&game, &path, &theme);
+            return;
         }
+        let mut playback = Playback::new(replay);
+        playback.speed = speed;
+        let mut drawer = Drawer::initialize_game(game.width() as u32, game.height() as u32, theme);
+        drawer.set_scale(scale, &game);
+        run_replay_loop(drawer, game, playback);
+        return;
+    }
+
+    let mut game = settings.new_game().unwrap_or_else(|err| {
+        println!("{}", err);
+        std::process::exit(1);
+    });
+    if let Some(path) = &load {
+        game = save::load(path).unwrap_or_else(|err| {
+            println!("Cannot load game from {}! {}", path.display(), err);
+            std::process::exit(1);
+        });
+    }
+
+    if let Some(path) = screenshot {
+        save_screenshot(&game, &path, &theme);
+        return;
+    }
+
+    let mut drawer = Drawer::initialize_game(game.width() as u32, game.height() as u32, theme);
+    drawer.set_themes(find_themes(classic));
+    drawer.set_scale(scale, &game);
+    drawer.set_chord_style(chord_style);
+    if let Some(path) = load {
+        drawer.set_save_file(path);
     }
-    let game = field::Game::new(w, h, mc);
-    let drawer = Drawer::initialize_game(game.width() as u32, game.height() as u32);
     run_game_loop(drawer, game);
 }
 
+/// The classic theme and the manifests in the `themes` directories in the
+/// working directory and next to the executable.
+fn find_themes(classic: ThemeSource) -> Vec<ThemeSource> {
+    let mut dirs = vec![PathBuf::from("themes")];
+    let exe = std::env::current_exe().ok();
+    if let Some(dir) = exe.as_deref().and_then(Path::parent) {
+        dirs.push(dir.join("themes"));
+    }
+
+    let mut themes = vec![classic];
+    for path in dirs.iter().flat_map(|dir| theme::find(dir)) {
+        let path = path.canonicalize().unwrap_or(path);
+        let theme = ThemeSource::Manifest(path);
+        if !themes.contains(&theme) {
+            themes.push(theme);
+        }
+    }
+    themes
+}
+
+fn save_screenshot(game: &Game, path: &Path, theme: &ThemeSource) {
+    let result = Renderer::new(theme)
+        .and_then(|renderer| renderer.render(game))
+        .and_then(|image| render::save_image(&image, path));
+    if let Err(err) = result {
+        println!("Cannot save screenshot to {}! {}", path.display(), err);
+        std::process::exit(1);
+    }
+}
+
+fn print_scores(path: &Path) {
+    let table = scores::load(path).unwrap_or_else(|err| {
+        println!("Cannot load scores from {}! {}", path.display(), err);
+        std::process::exit(1);
+    });
+
+    let mut empty = true;
+    for (&(w, h, mc), scores) in table.boards() {
+        let name = Preset::find(w, h, mc).map_or("custom", |p| p.name());
+        if !empty {
+            println!();
+        }
+        empty = false;
+        println!("{} {}x{}, {} mines", name, w, h, mc);
+        for (i, score) in scores.iter().enumerate() {
+            println!(
+                "{:>3}. {:>9.3} s  {}  {}",
+                i + 1,
+                score.time.as_secs_f64(),
+                score.date_string(),
+                score.name
+            );
+        }
+    }
+    if empty {
+        println!("No scores yet.");
+    }
+}
+
 fn run_game_loop(mut drawer: Drawer, mut game: Game) {
     let fps_time = Duration::from_secs_f64(1.0 / FPS);
     let mut i = 0.0;
@@ -58,3 +224,30 @@ fn run_game_loop(mut drawer: Drawer, mut game: Game) {
         next = now.checked_add(fps_time).unwrap();
     }
 }
+
+fn run_replay_loop(mut drawer: Drawer, mut game: Game, mut playback: Playback) {
+    let fps_time = Duration::from_secs_f64(1.0 / FPS);
+    let mut i = 0.0;
+    let mut last = Instant::now();
+    let mut next = last.checked_add(fps_time).unwrap();
+
+    loop {
+        if drawer.handle_replay_events(&mut playback) {
+            break;
+        }
+
+        let now = Instant::now();
+        playback.advance(&mut game, now.duration_since(last));
+        last = now;
+
+        i = (i + 0.03) % (std::f64::consts::PI * 2.0);
+        drawer.draw_screen(i, &game);
+
+        let now = Instant::now();
+        let remaining = next.duration_since(now);
+        if !remaining.is_zero() {
+            ::std::thread::sleep(remaining);
+        }
+        next = now.checked_add(fps_time).unwrap();
+    }
+}
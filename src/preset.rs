@@ -0,0 +1,174 @@
+//! Standard difficulty presets, validation of custom board settings and the
+//! board options shared by the command lines of all frontends.
+
+use crate::field::{FirstClickPolicy, Game, GameError};
+
+pub const MIN_SIZE: usize = 8;
+pub const MAX_SIZE: usize = 200;
+pub const MIN_MINES: i32 = 10;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Preset {
+    Beginner,
+    Intermediate,
+    Expert,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::Beginner, Preset::Intermediate, Preset::Expert];
+
+    /// Width, height and mine count.
+    pub fn settings(self) -> (usize, usize, i32) {
+        match self {
+            Preset::Beginner => (9, 9, 10),
+            Preset::Intermediate => (16, 16, 40),
+            Preset::Expert => (30, 16, 99),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Preset::Beginner => "beginner",
+            Preset::Intermediate => "intermediate",
+            Preset::Expert => "expert",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Preset::ALL.into_iter().find(|p| p.name() == name)
+    }
+
+    /// The preset with these settings, if any.
+    pub fn find(w: usize, h: usize, mine_count: i32) -> Option<Preset> {
+        Preset::ALL
+            .into_iter()
+            .find(|p| p.settings() == (w, h, mine_count))
+    }
+}
+
+/// Checks custom settings, leaving room for the tiles the first click policy
+/// keeps clear.
+pub fn check_settings(
+    w: usize,
+    h: usize,
+    mine_count: i32,
+    policy: FirstClickPolicy,
+) -> Result<(), String> {
+    if !(MIN_SIZE..=MAX_SIZE).contains(&w) || !(MIN_SIZE..=MAX_SIZE).contains(&h) {
+        return Err(format!(
+            "Width and height must be between {} and {}!",
+            MIN_SIZE, MAX_SIZE
+        ));
+    }
+    let max_mines = policy.max_mines(w, h);
+    if mine_count < MIN_MINES || mine_count > max_mines {
+        return Err(format!(
+            "Mine count must be between {} and {} for a {}x{} field!",
+            MIN_MINES, max_mines, w, h
+        ));
+    }
+    Ok(())
+}
+
+/// Board options given on the command line.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Settings {
+    pub w: usize,
+    pub h: usize,
+    pub mine_count: i32,
+    /// A random seed is picked when this is `None`.
+    pub seed: Option<u64>,
+    pub policy: FirstClickPolicy,
+    pub question_marks: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            w: 8,
+            h: 8,
+            mine_count: 10,
+            seed: None,
+            policy: FirstClickPolicy::Opening,
+            question_marks: false,
+        }
+    }
+}
+
+impl Settings {
+    /// Reads `--seed N`, `--no-guess`, `--marks`, `--first-click POLICY`,
+    /// `--preset NAME` and a custom width, height and mine count given as the
+    /// first three other arguments. Other arguments are ignored. The error
+    /// is a message for the user.
+    pub fn from_args<I: IntoIterator<Item = String>>(args: I) -> Result<Settings, String> {
+        let mut settings = Settings::default();
+        let mut positional = vec![];
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let mut value = || {
+                args.next()
+                    .ok_or_else(|| format!("Missing value for {}!", arg))
+            };
+            match arg.as_str() {
+                "--seed" => {
+                    let value = value()?;
+                    let seed = value
+                        .parse()
+                        .map_err(|_| "Cannot parse seed!".to_string())?;
+                    settings.seed = Some(seed);
+                }
+                "--no-guess" => settings.policy = FirstClickPolicy::NoGuess,
+                "--marks" => settings.question_marks = true,
+                "--first-click" => {
+                    let value = value()?;
+                    settings.policy = FirstClickPolicy::from_name(&value).ok_or_else(|| {
+                        format!(
+                            "Unknown first click policy '{}'!\nPolicies: none, safe, opening, no-guess",
+                            value
+                        )
+                    })?;
+                }
+                "--preset" => {
+                    let value = value()?;
+                    let preset = Preset::from_name(&value).ok_or_else(|| {
+                        format!(
+                            "Unknown preset '{}'!\nPresets: beginner, intermediate, expert",
+                            value
+                        )
+                    })?;
+                    (settings.w, settings.h, settings.mine_count) = preset.settings();
+                }
+                _ => positional.push(arg),
+            }
+        }
+
+        if positional.len() >= 3 {
+            let (w, h, mine_count) = (
+                positional[0]
+                    .parse()
+                    .map_err(|_| "Cannot parse first argument (field width)!".to_string())?,
+                positional[1]
+                    .parse()
+                    .map_err(|_| "Cannot parse second argument (field height)!".to_string())?,
+                positional[2]
+                    .parse()
+                    .map_err(|_| "Cannot parse third argument (mine count)!".to_string())?,
+            );
+            check_settings(w, h, mine_count, settings.policy).map_err(|err| {
+                format!(
+                    "Invalid parameters: width: {}, height: {}, mine count: {}!\n{}",
+                    w, h, mine_count, err
+                )
+            })?;
+            (settings.w, settings.h, settings.mine_count) = (w, h, mine_count);
+        }
+        Ok(settings)
+    }
+
+    pub fn new_game(&self) -> Result<Game, GameError> {
+        let seed = self.seed.unwrap_or_else(rand::random);
+        let mut game = Game::with_policy(self.w, self.h, self.mine_count, seed, self.policy)?;
+        game.set_question_marks(self.question_marks);
+        Ok(game)
+    }
+}
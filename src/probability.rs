@@ -0,0 +1,415 @@
+//! Exact mine probabilities for hidden tiles, computed from the visible board
+//! like the [`solver`](crate::solver) does.
+//!
+//! Hidden tiles next to revealed numbers are split into independent
+//! components and every mine layout of each component is counted, taking
+//! tiles touched by the same numbers together. The search is capped, so very
+//! open frontiers give up instead of stalling the game. The layouts are then
+//! weighted by the number of ways to place the rest of the remaining mines on
+//! the tiles that no number touches.
+
+use crate::field::Game;
+use crate::solver::{self, Cell, Constraint};
+
+/// Search steps allowed for one call. Boards that need more are reported as
+/// unknown rather than blocking the caller.
+const MAX_STEPS: usize = 250_000;
+
+struct Component {
+    cells: Vec<(usize, usize)>,
+    // Relative number of layouts with `k` mines, indexed by `k`.
+    layouts: Vec<f64>,
+    // Relative number of layouts with `k` mines that have a mine on each cell.
+    mines: Vec<Vec<f64>>,
+}
+
+/// Cells touched by exactly the same numbers. Any layout of a group's mines
+/// fits the numbers equally well, so only how many there are matters.
+struct Group {
+    cells: Vec<usize>,
+    constraints: Vec<usize>,
+}
+
+struct Search<'g> {
+    groups: &'g [Group],
+    binomials: &'g [Vec<f64>],
+    targets: Vec<i32>,
+    placed: Vec<i32>,
+    open: Vec<i32>,
+    assignment: Vec<usize>,
+    layouts: Vec<f64>,
+    // Like `Component::mines`, but per group and summed over its cells.
+    mines: Vec<Vec<f64>>,
+    steps: usize,
+}
+
+impl Search<'_> {
+    /// Returns false when it runs out of steps.
+    fn run(&mut self, i: usize, k: usize, ways: f64) -> bool {
+        self.steps += 1;
+        if self.steps > MAX_STEPS {
+            return false;
+        }
+        if i == self.groups.len() {
+            self.layouts[k] += ways;
+            for (group, &n) in self.assignment.iter().enumerate() {
+                self.mines[k][group] += ways * n as f64;
+            }
+            return true;
+        }
+
+        let group = &self.groups[i];
+        let size = group.cells.len() as i32;
+        for n in 0..=size {
+            // `open` still counts this group, so the rest can hold
+            // `open - size` more mines.
+            let fits = group.constraints.iter().all(|&c| {
+                let placed = self.placed[c] + n;
+                placed <= self.targets[c] && placed + self.open[c] - size >= self.targets[c]
+            });
+            if !fits {
+                continue;
+            }
+
+            for &c in &group.constraints {
+                self.placed[c] += n;
+                self.open[c] -= size;
+            }
+            self.assignment[i] = n as usize;
+            let ways = ways * self.binomials[size as usize][n as usize];
+            let finished = self.run(i + 1, k + n as usize, ways);
+            for &c in &group.constraints {
+                self.placed[c] -= n;
+                self.open[c] += size;
+            }
+            if !finished {
+                return false;
+            }
+        }
+        self.assignment[i] = 0;
+        true
+    }
+}
+
+/// Splits the constraints into independent components and counts the layouts
+/// of each. Returns `None` when that takes more than [`MAX_STEPS`].
+fn components(constraints: &[Constraint], w: usize, h: usize) -> Option<Vec<Component>> {
+    let mut touching = vec![vec![vec![]; h]; w];
+    for (i, c) in constraints.iter().enumerate() {
+        for &(x, y) in &c.cells {
+            touching[x][y].push(i);
+        }
+    }
+
+    // A number touches at most eight tiles.
+    let mut binomials = vec![vec![1.0]];
+    for n in 1..=8 {
+        let last: &Vec<f64> = &binomials[n - 1];
+        let row = (0..=n)
+            .map(|k| {
+                if k == 0 || k == n {
+                    1.0
+                } else {
+                    last[k - 1] + last[k]
+                }
+            })
+            .collect();
+        binomials.push(row);
+    }
+
+    let mut visited = vec![false; constraints.len()];
+    let mut result = vec![];
+    let mut steps = 0;
+    for start in 0..constraints.len() {
+        if visited[start] {
+            continue;
+        }
+
+        // Walk overlapping constraints breadth first, which also gives a cell
+        // order where constraints get closed early during the search.
+        let mut members = vec![start];
+        visited[start] = true;
+        let mut cells: Vec<(usize, usize)> = vec![];
+        let mut next = 0;
+        while next < members.len() {
+            for &(x, y) in &constraints[members[next]].cells {
+                if !cells.contains(&(x, y)) {
+                    cells.push((x, y));
+                }
+                for &other in &touching[x][y] {
+                    if !visited[other] {
+                        visited[other] = true;
+                        members.push(other);
+                    }
+                }
+            }
+            next += 1;
+        }
+
+        let mut groups: Vec<Group> = vec![];
+        for (index, &(x, y)) in cells.iter().enumerate() {
+            let mut touched: Vec<usize> = touching[x][y]
+                .iter()
+                .map(|c| members.iter().position(|m| m == c).unwrap())
+                .collect();
+            touched.sort();
+            match groups.iter_mut().find(|g| g.constraints == touched) {
+                Some(group) => group.cells.push(index),
+                None => groups.push(Group {
+                    cells: vec![index],
+                    constraints: touched,
+                }),
+            }
+        }
+
+        let mut search = Search {
+            groups: &groups,
+            binomials: &binomials,
+            targets: members.iter().map(|&m| constraints[m].mines).collect(),
+            placed: vec![0; members.len()],
+            open: members
+                .iter()
+                .map(|&m| constraints[m].cells.len() as i32)
+                .collect(),
+            assignment: vec![0; groups.len()],
+            layouts: vec![0.0; cells.len() + 1],
+            mines: vec![vec![0.0; groups.len()]; cells.len() + 1],
+            steps,
+        };
+        if !search.run(0, 0, 1.0) {
+            return None;
+        }
+        steps = search.steps;
+
+        // Only ratios matter, so keep the counts small to avoid overflow.
+        let scale = search.layouts.iter().cloned().fold(0.0, f64::max);
+        if scale > 0.0 {
+            search.layouts.iter_mut().for_each(|n| *n /= scale);
+            search.mines.iter_mut().flatten().for_each(|n| *n /= scale);
+        }
+
+        // Every layout of a group is equally likely, so its mines are spread
+        // evenly over its cells.
+        let mut mines = vec![vec![0.0; cells.len()]; cells.len() + 1];
+        for (k, per_group) in search.mines.iter().enumerate() {
+            for (group, &m) in groups.iter().zip(per_group) {
+                for &cell in &group.cells {
+                    mines[k][cell] = m / group.cells.len() as f64;
+                }
+            }
+        }
+
+        result.push(Component {
+            cells,
+            layouts: search.layouts,
+            mines,
+        });
+    }
+    Some(result)
+}
+
+fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut result = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            result[i + j] += x * y;
+        }
+    }
+    result
+}
+
+/// Returns the probability of a mine for every hidden, unflagged tile, and
+/// `None` for all other tiles. Flags are trusted to be correct. Returns
+/// `None` when the visible board has no consistent mine layout, or when it
+/// has too many to count quickly.
+pub fn mine_probabilities(game: &Game) -> Option<Vec<Vec<Option<f64>>>> {
+    let (w, h) = game.size();
+    let cells = solver::visible_cells(game.get_field());
+    let components = components(&solver::constraints(&cells), w, h)?;
+
+    let mut result = vec![vec![None; h]; w];
+    for c in &components {
+        for &(x, y) in &c.cells {
+            result[x][y] = Some(0.0);
+        }
+    }
+    let interior: Vec<(usize, usize)> = (0..w)
+        .flat_map(|x| (0..h).map(move |y| (x, y)))
+        .filter(|&(x, y)| cells[x][y] == Cell::Hidden && result[x][y].is_none())
+        .collect();
+
+    // Weight of each possible number of mines on the frontier: the number of
+    // ways to place the rest on interior tiles, relative to the largest one.
+    let remaining = game.mines_remaining() as usize;
+    let frontier: usize = components.iter().map(|c| c.cells.len()).sum();
+    let mut ln_factorial = vec![0.0; w * h + 1];
+    for n in 1..ln_factorial.len() {
+        ln_factorial[n] = ln_factorial[n - 1] + (n as f64).ln();
+    }
+    let ln_ways: Vec<Option<f64>> = (0..=frontier)
+        .map(|t| {
+            let rest = remaining.checked_sub(t)?;
+            let free = interior.len().checked_sub(rest)?;
+            Some(ln_factorial[interior.len()] - ln_factorial[rest] - ln_factorial[free])
+        })
+        .collect();
+    let most = ln_ways.iter().flatten().cloned().fold(f64::MIN, f64::max);
+    let weights: Vec<f64> = ln_ways
+        .iter()
+        .map(|w| w.map_or(0.0, |w| (w - most).exp()))
+        .collect();
+
+    let total = components
+        .iter()
+        .fold(vec![1.0], |acc, c| convolve(&acc, &c.layouts));
+    let z: f64 = total.iter().zip(&weights).map(|(n, w)| n * w).sum();
+    if z <= 0.0 {
+        return None;
+    }
+
+    for (i, c) in components.iter().enumerate() {
+        let others = components
+            .iter()
+            .enumerate()
+            .filter(|(j, _)| *j != i)
+            .fold(vec![1.0], |acc, (_, c)| convolve(&acc, &c.layouts));
+
+        for (k, mines) in c.mines.iter().enumerate() {
+            let weight: f64 = others
+                .iter()
+                .enumerate()
+                .filter(|(t, _)| k + t < weights.len())
+                .map(|(t, n)| n * weights[k + t])
+                .sum();
+            for (&(x, y), m) in c.cells.iter().zip(mines) {
+                if let Some(p) = result[x][y].as_mut() {
+                    *p += m * weight / z;
+                }
+            }
+        }
+    }
+
+    if !interior.is_empty() {
+        let expected: f64 = total
+            .iter()
+            .zip(&weights)
+            .enumerate()
+            .map(|(t, (n, w))| n * w * remaining.saturating_sub(t) as f64)
+            .sum();
+        let p = expected / z / interior.len() as f64;
+        for &(x, y) in &interior {
+            result[x][y] = Some(p);
+        }
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::{FirstClickPolicy, TileContent};
+
+    /// Probabilities from trying every placement of the remaining mines on
+    /// the hidden tiles.
+    fn brute_force(game: &Game) -> Vec<Vec<Option<f64>>> {
+        let (w, h) = game.size();
+        let field = game.get_field();
+        let hidden: Vec<(usize, usize)> = (0..w)
+            .flat_map(|x| (0..h).map(move |y| (x, y)))
+            .filter(|&(x, y)| !field[x][y].revealed && !field[x][y].flagged)
+            .collect();
+
+        fn place(
+            game: &Game,
+            hidden: &[(usize, usize)],
+            mines: &mut Vec<Vec<bool>>,
+            from: usize,
+            left: usize,
+            counts: &mut (f64, Vec<Vec<f64>>),
+        ) {
+            if left == 0 {
+                let (w, h) = game.size();
+                let field = game.get_field();
+                for x in 0..w {
+                    for y in 0..h {
+                        if let (true, TileContent::Empty(n)) =
+                            (field[x][y].revealed, &field[x][y].content)
+                        {
+                            let mut around = 0;
+                            Game::do_for_neightbors(
+                                x,
+                                y,
+                                |x, y| around += (mines[x][y] || field[x][y].flagged) as i32,
+                                w,
+                                h,
+                            );
+                            if around != *n {
+                                return;
+                            }
+                        }
+                    }
+                }
+                counts.0 += 1.0;
+                for &(x, y) in hidden {
+                    counts.1[x][y] += mines[x][y] as i32 as f64;
+                }
+                return;
+            }
+            for i in from..hidden.len() {
+                let (x, y) = hidden[i];
+                mines[x][y] = true;
+                place(game, hidden, mines, i + 1, left - 1, counts);
+                mines[x][y] = false;
+            }
+        }
+
+        let mut counts = (0.0, vec![vec![0.0; h]; w]);
+        let remaining = game.mines_remaining() as usize;
+        place(
+            game,
+            &hidden,
+            &mut vec![vec![false; h]; w],
+            0,
+            remaining,
+            &mut counts,
+        );
+
+        let mut result = vec![vec![None; h]; w];
+        for &(x, y) in &hidden {
+            result[x][y] = Some(counts.1[x][y] / counts.0);
+        }
+        result
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        for seed in 0..100 {
+            let mut game = Game::with_policy(5, 5, 5, seed, FirstClickPolicy::SafeCell).unwrap();
+            let (x, y) = (seed as usize % 5, seed as usize / 5 % 5);
+            game.reveal_tile(x, y).unwrap();
+            // Flag one real mine, so flags are part of the board too.
+            let (w, h) = game.size();
+            let mine = (0..w)
+                .flat_map(|x| (0..h).map(move |y| (x, y)))
+                .find(|&(x, y)| matches!(game.get_field()[x][y].content, TileContent::Bomb));
+            if let (Some((x, y)), true) = (mine, seed % 2 == 0) {
+                game.flag_tile(x, y).unwrap();
+            }
+            if !matches!(game.state(), crate::field::GameState::Playing(_)) {
+                continue;
+            }
+
+            let expected = brute_force(&game);
+            let actual = mine_probabilities(&game).unwrap();
+            for x in 0..w {
+                for y in 0..h {
+                    match (expected[x][y], actual[x][y]) {
+                        (Some(e), Some(a)) => assert!((e - a).abs() < 1e-9, "seed {}", seed),
+                        (e, a) => assert_eq!(e.is_some(), a.is_some(), "seed {}", seed),
+                    }
+                }
+            }
+        }
+    }
+}
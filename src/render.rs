@@ -0,0 +1,485 @@
+//! Drawing the board onto any SDL surface, the window or an offscreen one
+//! that can be saved as an image.
+
+use sdl2::pixels::{Color, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::BlendMode;
+use sdl2::rwops::RWops;
+use sdl2::surface::{Surface, SurfaceRef};
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::field::{Game, GameState, Hint, TileContent};
+use crate::theme::{self, Sprite, Theme};
+
+const RISK_STEPS: usize = 20;
+
+/// A 3x5 pixel font for labels, one row per byte from the top with the
+/// leftmost pixel in bit 2. Letters are upper case only.
+const FONT: [(char, [u8; 5]); 43] = [
+    (' ', [0b000, 0b000, 0b000, 0b000, 0b000]),
+    ('0', [0b111, 0b101, 0b101, 0b101, 0b111]),
+    ('1', [0b010, 0b110, 0b010, 0b010, 0b111]),
+    ('2', [0b111, 0b001, 0b111, 0b100, 0b111]),
+    ('3', [0b111, 0b001, 0b111, 0b001, 0b111]),
+    ('4', [0b101, 0b101, 0b111, 0b001, 0b001]),
+    ('5', [0b111, 0b100, 0b111, 0b001, 0b111]),
+    ('6', [0b111, 0b100, 0b111, 0b101, 0b111]),
+    ('7', [0b111, 0b001, 0b001, 0b001, 0b001]),
+    ('8', [0b111, 0b101, 0b111, 0b101, 0b111]),
+    ('9', [0b111, 0b101, 0b111, 0b001, 0b111]),
+    ('A', [0b010, 0b101, 0b111, 0b101, 0b101]),
+    ('B', [0b110, 0b101, 0b110, 0b101, 0b110]),
+    ('C', [0b011, 0b100, 0b100, 0b100, 0b011]),
+    ('D', [0b110, 0b101, 0b101, 0b101, 0b110]),
+    ('E', [0b111, 0b100, 0b110, 0b100, 0b111]),
+    ('F', [0b111, 0b100, 0b110, 0b100, 0b100]),
+    ('G', [0b011, 0b100, 0b101, 0b101, 0b011]),
+    ('H', [0b101, 0b101, 0b111, 0b101, 0b101]),
+    ('I', [0b111, 0b010, 0b010, 0b010, 0b111]),
+    ('J', [0b001, 0b001, 0b001, 0b101, 0b010]),
+    ('K', [0b101, 0b101, 0b110, 0b101, 0b101]),
+    ('L', [0b100, 0b100, 0b100, 0b100, 0b111]),
+    ('M', [0b101, 0b111, 0b111, 0b101, 0b101]),
+    ('N', [0b110, 0b101, 0b101, 0b101, 0b101]),
+    ('O', [0b010, 0b101, 0b101, 0b101, 0b010]),
+    ('P', [0b110, 0b101, 0b110, 0b100, 0b100]),
+    ('Q', [0b010, 0b101, 0b101, 0b110, 0b011]),
+    ('R', [0b110, 0b101, 0b110, 0b101, 0b101]),
+    ('S', [0b011, 0b100, 0b010, 0b001, 0b110]),
+    ('T', [0b111, 0b010, 0b010, 0b010, 0b010]),
+    ('U', [0b101, 0b101, 0b101, 0b101, 0b111]),
+    ('V', [0b101, 0b101, 0b101, 0b101, 0b010]),
+    ('W', [0b101, 0b101, 0b111, 0b111, 0b101]),
+    ('X', [0b101, 0b101, 0b010, 0b101, 0b101]),
+    ('Y', [0b101, 0b101, 0b010, 0b010, 0b010]),
+    ('Z', [0b111, 0b001, 0b010, 0b100, 0b111]),
+    ('.', [0b000, 0b000, 0b000, 0b000, 0b010]),
+    (':', [0b000, 0b010, 0b000, 0b010, 0b000]),
+    ('-', [0b000, 0b000, 0b111, 0b000, 0b000]),
+    ('%', [0b101, 0b001, 0b010, 0b100, 0b101]),
+    ('/', [0b001, 0b001, 0b010, 0b100, 0b100]),
+    ('?', [0b111, 0b001, 0b010, 0b000, 0b010]),
+];
+
+/// Sprite sheets built into the binary, by file name.
+const EMBEDDED: [(&str, &[u8]); 4] = [
+    ("borders.bmp", include_bytes!("../assets/borders.bmp")),
+    ("numbers.bmp", include_bytes!("../assets/numbers.bmp")),
+    ("faces.bmp", include_bytes!("../assets/faces.bmp")),
+    ("tiles.bmp", include_bytes!("../assets/tiles.bmp")),
+];
+
+/// Where a renderer gets its theme and sprite sheets from.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum ThemeSource {
+    /// The built-in theme, with the sheets from an assets directory or the
+    /// embedded ones if `None`. Every sheet must be present in the directory.
+    Classic(Option<PathBuf>),
+    /// A theme manifest. Its sheets are relative to it.
+    Manifest(PathBuf),
+}
+
+impl ThemeSource {
+    fn load<'a>(&self) -> Result<(Theme, Vec<Surface<'a>>), String> {
+        let (theme, dir) = match self {
+            ThemeSource::Classic(dir) => (Theme::classic(), dir.as_deref()),
+            ThemeSource::Manifest(path) => {
+                let theme = theme::load(path)
+                    .map_err(|err| format!("Cannot load theme {}! {}", path.display(), err))?;
+                (theme, Some(path.parent().unwrap_or(Path::new(""))))
+            }
+        };
+        let sheets = theme
+            .sheets
+            .iter()
+            .map(|file| match dir {
+                Some(dir) => {
+                    let path = dir.join(file);
+                    Surface::load_bmp(&path)
+                        .map_err(|err| format!("Cannot load {}! {}", path.display(), err))
+                }
+                None => {
+                    let (_, bytes) = EMBEDDED
+                        .iter()
+                        .find(|(name, _)| Path::new(name) == file)
+                        .ok_or_else(|| format!("No embedded sheet {}!", file.display()))?;
+                    RWops::from_bytes(bytes)
+                        .and_then(|mut bmp| Surface::load_bmp_rw(&mut bmp))
+                        .map_err(|err| format!("Cannot load embedded {}! {}", file.display(), err))
+                }
+            })
+            .collect::<Result<_, _>>()?;
+        Ok((theme, sheets))
+    }
+}
+
+/// The directory to load sprite sheets from instead of the embedded ones:
+/// `dir` if given, otherwise an `assets` directory next to the executable if
+/// there is one.
+pub fn assets_dir(dir: Option<PathBuf>) -> Option<PathBuf> {
+    dir.or_else(|| {
+        let exe = std::env::current_exe().ok()?;
+        Some(exe.parent()?.join("assets")).filter(|dir| dir.is_dir())
+    })
+}
+
+/// What is drawn on top of the tiles besides the game itself.
+#[derive(Clone, Copy, Default)]
+pub struct Overlay<'o> {
+    /// Mine probability of every hidden tile, tinted from green to red.
+    pub probabilities: Option<&'o [Vec<Option<f64>>]>,
+    pub hint: Option<Hint>,
+    /// Keyboard cursor.
+    pub cursor: Option<(usize, usize)>,
+}
+
+pub struct Renderer<'a> {
+    theme: Theme,
+    sheets: Vec<Surface<'a>>,
+    risk_tints: Vec<Surface<'a>>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(source: &ThemeSource) -> Result<Renderer<'a>, String> {
+        let (theme, sheets) = source.load()?;
+        let size = theme.tile_size;
+        let risk_tints = (0..=RISK_STEPS)
+            .map(|i| {
+                let p = i as f64 / RISK_STEPS as f64;
+                let mut tint = Surface::new(size, size, PixelFormatEnum::RGB888)
+                    .expect("Cannot create overlay surface!");
+                tint.fill_rect(
+                    None,
+                    Color::RGB((255.0 * p) as u8, (255.0 * (1.0 - p)) as u8, 0),
+                )
+                .unwrap();
+                tint.set_blend_mode(BlendMode::Blend)
+                    .expect("Cannot set overlay blend mode!");
+                tint.set_alpha_mod(112);
+                tint
+            })
+            .collect();
+
+        Ok(Renderer {
+            theme,
+            sheets,
+            risk_tints,
+        })
+    }
+
+    pub fn theme(&self) -> &Theme {
+        &self.theme
+    }
+
+    /// Size in pixels of the screen showing a board of `w` by `h` tiles.
+    pub fn screen_size(&self, w: usize, h: usize) -> (u32, u32) {
+        self.theme.screen_size(w, h)
+    }
+
+    /// Where the tile at `x`, `y` is drawn.
+    pub fn tile_rect(&self, x: usize, y: usize) -> Rect {
+        let (ox, oy) = self.theme.field_origin();
+        let size = self.theme.tile_size;
+        Rect::new(
+            ox + x as i32 * size as i32,
+            oy + y as i32 * size as i32,
+            size,
+            size,
+        )
+    }
+
+    /// The tile under the pixel at `x`, `y`, if any.
+    pub fn tile_at(&self, game: &Game, x: i32, y: i32) -> Option<(usize, usize)> {
+        let (ox, oy) = self.theme.field_origin();
+        if x < ox || y < oy {
+            return None;
+        }
+        let size = self.theme.tile_size as i32;
+        let (x, y) = (((x - ox) / size) as usize, ((y - oy) / size) as usize);
+        if x < game.width() && y < game.height() {
+            Some((x, y))
+        } else {
+            None
+        }
+    }
+
+    /// Where the face is drawn on a screen `width` pixels wide.
+    pub fn face_rect(&self, width: u32) -> Rect {
+        let face = self.theme.face;
+        Rect::new(
+            (width as i32 - face.w as i32) / 2,
+            self.theme.face_y,
+            face.w,
+            face.h,
+        )
+    }
+
+    fn blit(&self, sprite: Sprite, screen: &mut SurfaceRef, x: i32, y: i32) {
+        self.sheets[sprite.sheet]
+            .blit(
+                Rect::new(sprite.x, sprite.y, sprite.w, sprite.h),
+                screen,
+                Rect::new(x, y, sprite.w, sprite.h),
+            )
+            .unwrap();
+    }
+
+    /// Fills the screen with a color that cycles as `i` goes from 0 to 2π.
+    pub fn draw_background(screen: &mut SurfaceRef, i: f64) {
+        let color = Color::RGB(
+            (((i).cos() + 1.0) * 126.0).round() as u8,
+            64,
+            (((i).sin() + 1.0) * 126.0).round() as u8,
+        );
+
+        screen.fill_rect(None, color).unwrap();
+    }
+
+    /// Draws the borders, tiles, counters and face.
+    pub fn draw_board(&self, screen: &mut SurfaceRef, game: &Game, overlay: &Overlay) {
+        self.draw_borders(screen, game);
+        self.draw_field(screen, game, overlay);
+        self.draw_numbers(screen, game);
+        self.draw_face(screen, game);
+    }
+
+    /// Draws the board onto a new offscreen surface the size of the window.
+    pub fn render(&self, game: &Game) -> Result<Surface<'static>, String> {
+        let (w, h) = self.screen_size(game.width(), game.height());
+        let mut screen = Surface::new(w, h, PixelFormatEnum::RGB24)?;
+        Renderer::draw_background(&mut screen, 0.0);
+        self.draw_board(&mut screen, game, &Overlay::default());
+        Ok(screen)
+    }
+
+    pub fn draw_borders(&self, screen: &mut SurfaceRef, game: &Game) {
+        let t = &self.theme;
+        let (w, h) = screen.size();
+        let (w, h) = (w as i32, h as i32);
+        let (ox, oy) = t.field_origin();
+        let size = t.tile_size as i32;
+
+        // The top is filled first and the corners and the piece behind the
+        // face are drawn over it.
+        let mut x = t.border_top_left.w as i32;
+        while x < w - t.border_top_right.w as i32 {
+            self.blit(t.border_top, screen, x, 0);
+            x += t.border_top.w as i32;
+        }
+        self.blit(t.border_top_left, screen, 0, 0);
+        self.blit(
+            t.border_top_right,
+            screen,
+            w - t.border_top_right.w as i32,
+            0,
+        );
+        self.blit(
+            t.border_top_middle,
+            screen,
+            (w - t.border_top_middle.w as i32) / 2,
+            0,
+        );
+
+        for y in 0..game.height() as i32 {
+            self.blit(t.border_left, screen, 0, oy + y * size);
+            self.blit(
+                t.border_right,
+                screen,
+                w - t.border_right.w as i32,
+                oy + y * size,
+            );
+        }
+        for x in 0..game.width() as i32 {
+            self.blit(
+                t.border_bottom,
+                screen,
+                ox + x * size,
+                h - t.border_bottom.h as i32,
+            );
+        }
+        self.blit(
+            t.border_bottom_left,
+            screen,
+            0,
+            h - t.border_bottom_left.h as i32,
+        );
+        self.blit(
+            t.border_bottom_right,
+            screen,
+            w - t.border_bottom_right.w as i32,
+            h - t.border_bottom_right.h as i32,
+        );
+    }
+
+    pub fn draw_field(&self, screen: &mut SurfaceRef, game: &Game, overlay: &Overlay) {
+        let t = &self.theme;
+        let field = game.get_field();
+        let playing = matches!(game.state(), GameState::Playing(_));
+
+        for (x, row) in field.iter().enumerate() {
+            for (y, tile) in row.iter().enumerate() {
+                let hidden = if game.preview_at(x, y) {
+                    t.pressed
+                } else if tile.questioned {
+                    t.question
+                } else {
+                    t.hidden
+                };
+                let sprite = match (&tile.revealed, &tile.flagged, &tile.content) {
+                    (true, false, TileContent::Bomb) => t.exploded,
+                    (true, true, TileContent::Empty(_)) => t.wrong_flag,
+                    (true, true, TileContent::Bomb) => t.flag,
+                    (true, false, TileContent::Empty(i)) => t.tiles[*i as usize],
+                    (false, false, TileContent::Bomb) if !playing => t.mine,
+                    (false, false, _) => hidden,
+                    (false, true, _) => t.flag,
+                };
+
+                let dst = self.tile_rect(x, y);
+                self.blit(sprite, screen, dst.x(), dst.y());
+
+                if !playing {
+                    continue;
+                }
+                if let Some(Some(p)) = overlay.probabilities.map(|p| p[x][y]) {
+                    let tint = &self.risk_tints[(p * RISK_STEPS as f64).round() as usize];
+                    tint.blit(None, screen, dst).unwrap();
+                }
+                if let Some(hint) = overlay.hint {
+                    if (hint.x, hint.y) == (x, y) && !tile.revealed && !tile.flagged {
+                        Renderer::draw_hint(screen, dst, hint);
+                    }
+                }
+            }
+        }
+
+        if let Some((x, y)) = overlay.cursor {
+            if x < game.width() && y < game.height() {
+                let dst = self.tile_rect(x, y);
+                Renderer::draw_frame(screen, dst, 2, Color::RGB(0, 96, 255));
+            }
+        }
+    }
+
+    fn draw_hint(screen: &mut SurfaceRef, dst: Rect, hint: Hint) {
+        let color = if hint.mine_probability == 0.0 {
+            Color::RGB(0, 224, 0)
+        } else {
+            Color::RGB(255, 192, 0)
+        };
+        Renderer::draw_frame(screen, dst, 2, color);
+    }
+
+    pub fn draw_frame(screen: &mut SurfaceRef, rect: Rect, width: u32, color: Color) {
+        let (x, y, w, h) = (rect.x(), rect.y(), rect.width(), rect.height());
+        let frame = [
+            Rect::new(x, y, w, width),
+            Rect::new(x, y + (h - width) as i32, w, width),
+            Rect::new(x, y, width, h),
+            Rect::new(x + (w - width) as i32, y, width, h),
+        ];
+        screen.fill_rects(&frame, color).unwrap();
+    }
+
+    /// Width in pixels of `text` drawn with [`Renderer::draw_text`].
+    pub fn text_width(text: &str, size: u32) -> u32 {
+        (text.chars().count() as u32 * 4).saturating_sub(1) * size
+    }
+
+    /// Draws `text` with its top left corner at `x`, `y`, every font pixel
+    /// `size` pixels wide. Characters missing from the font are drawn as `?`.
+    pub fn draw_text(screen: &mut SurfaceRef, x: i32, y: i32, text: &str, size: u32, color: Color) {
+        let mut pixels = vec![];
+        for (i, c) in text.to_ascii_uppercase().chars().enumerate() {
+            let (_, rows) = FONT
+                .iter()
+                .find(|(f, _)| *f == c)
+                .or_else(|| FONT.iter().find(|(f, _)| *f == '?'))
+                .unwrap();
+            let left = x + (i as u32 * 4 * size) as i32;
+            for (row, bits) in rows.iter().enumerate() {
+                for column in 0..3 {
+                    if bits & (0b100 >> column) != 0 {
+                        pixels.push(Rect::new(
+                            left + (column * size) as i32,
+                            y + (row as u32 * size) as i32,
+                            size,
+                            size,
+                        ));
+                    }
+                }
+            }
+        }
+        screen.fill_rects(&pixels, color).unwrap();
+    }
+
+    pub fn draw_numbers(&self, screen: &mut SurfaceRef, game: &Game) {
+        let (x, y, spacing) = self.theme.counters;
+        let digit = self.theme.digits[0].w as i32;
+        self.draw_number(screen, x + 2 * spacing, y, game.mines_remaining());
+        let right = screen.width() as i32 - x - digit;
+        self.draw_number(screen, right, y, game.time());
+    }
+
+    /// Draws the last three digits of `number`, the last one at `x`.
+    pub fn draw_number(&self, screen: &mut SurfaceRef, x: i32, y: i32, number: i32) {
+        let spacing = self.theme.counters.2;
+        let mut number = number;
+        for i in 0..3 {
+            let n = number % 10;
+            number /= 10;
+
+            self.blit(self.theme.digits[n as usize], screen, x - i * spacing, y);
+        }
+    }
+
+    pub fn draw_face(&self, screen: &mut SurfaceRef, game: &Game) {
+        let face = match game.state() {
+            GameState::Boom(_) => self.theme.face_lost,
+            GameState::Victory(_) => self.theme.face_won,
+            GameState::Playing(_) => {
+                if game.preview() {
+                    self.theme.face_pressed
+                } else {
+                    self.theme.face
+                }
+            }
+        };
+
+        let dst = self.face_rect(screen.width());
+        self.blit(face, screen, dst.x(), dst.y());
+    }
+}
+
+/// Saves a surface as PNG if the file name ends with `.png`, otherwise as
+/// BMP.
+pub fn save_image(surface: &SurfaceRef, path: &Path) -> Result<(), String> {
+    let png = path
+        .extension()
+        .is_some_and(|e| e.eq_ignore_ascii_case("png"));
+    if !png {
+        return surface.save_bmp(path);
+    }
+
+    let surface = surface.convert_format(PixelFormatEnum::RGB24)?;
+    let (w, h) = surface.size();
+    let pitch = surface.pitch() as usize;
+    let data: Vec<u8> = surface
+        .without_lock()
+        .ok_or("Cannot read surface pixels!")?
+        .chunks(pitch)
+        .flat_map(|row| &row[..w as usize * 3])
+        .copied()
+        .collect();
+
+    let file = File::create(path).map_err(|e| e.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), w, h);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&data))
+        .map_err(|e| e.to_string())
+}
@@ -0,0 +1,276 @@
+//! Recording every input a game receives and playing it back.
+//!
+//! A replay file is a [text file](crate::format) with one line per input after
+//! the header and then the board as it was when the recording started:
+//!
+//! ```text
+//! minesweeper-replay 2
+//! 0 new 1234567890
+//! 1250 reveal 4 4
+//! 2730 flag 3 5
+//! 3015 chord 4 5
+//! 4100 undo
+//! 4600 redo
+//! 5200 hint
+//! board
+//! minesweeper-save 5
+//! ...
+//! ```
+//!
+//! Every input line starts with the time in milliseconds since the game was
+//! set up, followed by the input: `new <seed>`, `reveal <x> <y>`,
+//! `chord <x> <y>`, `flag <x> <y>`, `undo`, `redo`, `hint` or `marks on|off`
+//! for turning question marks on or off. Coordinates count tiles from the top
+//! left corner. Version 1 files have no `hint` inputs. The board after
+//! `board` uses the save file format described in [`save`](crate::save), so
+//! replays of loaded games start from the loaded position.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::field::{Game, GameError};
+use crate::format;
+use crate::save;
+
+const HEADER: &str = "minesweeper-replay";
+const VERSION: u32 = 2;
+
+/// Slowest and fastest playback speeds.
+pub const MIN_SPEED: f64 = 1.0 / 16.0;
+pub const MAX_SPEED: f64 = 16.0;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Action {
+    NewGame(u64),
+    Reveal(usize, usize),
+    Chord(usize, usize),
+    Flag(usize, usize),
+    Undo,
+    Redo,
+    Hint,
+    QuestionMarks(bool),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Input {
+    pub time: Duration,
+    pub action: Action,
+}
+
+#[derive(Clone)]
+pub struct Replay {
+    start: String,
+    inputs: Vec<Input>,
+}
+
+impl Replay {
+    pub(crate) fn new(start: String, inputs: Vec<Input>) -> Replay {
+        Replay { start, inputs }
+    }
+
+    pub fn inputs(&self) -> &[Input] {
+        &self.inputs
+    }
+
+    /// The game as it was when the recording started.
+    pub fn start(&self) -> Game {
+        save::from_str(&self.start).expect("Replay holds an invalid board!")
+    }
+}
+
+pub fn save(replay: &Replay, path: &Path) -> Result<()> {
+    fs::write(path, to_string(replay))
+}
+
+pub fn load(path: &Path) -> Result<Replay> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+pub fn to_string(replay: &Replay) -> String {
+    let mut text = format!("{} {}\n", HEADER, VERSION);
+    for input in &replay.inputs {
+        let action = match input.action {
+            Action::NewGame(seed) => format!("new {}", seed),
+            Action::Reveal(x, y) => format!("reveal {} {}", x, y),
+            Action::Chord(x, y) => format!("chord {} {}", x, y),
+            Action::Flag(x, y) => format!("flag {} {}", x, y),
+            Action::Undo => "undo".to_string(),
+            Action::Redo => "redo".to_string(),
+            Action::Hint => "hint".to_string(),
+            Action::QuestionMarks(true) => "marks on".to_string(),
+            Action::QuestionMarks(false) => "marks off".to_string(),
+        };
+        text += &format!("{} {}\n", input.time.as_millis(), action);
+    }
+    text += "board\n";
+    text += &replay.start;
+    text
+}
+
+pub fn from_str(text: &str) -> Result<Replay> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+
+    let mut lines = text.lines();
+    format::check_header(&mut lines, HEADER, 1..=VERSION, "replay")?;
+
+    let mut inputs = vec![];
+    for line in lines.by_ref() {
+        if line == "board" {
+            break;
+        }
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| -> Result<u64> {
+            parts
+                .get(i)
+                .and_then(|p| p.parse().ok())
+                .ok_or_else(|| invalid(format!("Invalid replay input '{}'!", line)))
+        };
+        let action = match parts.get(1) {
+            Some(&"new") => Action::NewGame(number(2)?),
+            Some(&"reveal") => Action::Reveal(number(2)? as usize, number(3)? as usize),
+            Some(&"chord") => Action::Chord(number(2)? as usize, number(3)? as usize),
+            Some(&"flag") => Action::Flag(number(2)? as usize, number(3)? as usize),
+            Some(&"undo") => Action::Undo,
+            Some(&"redo") => Action::Redo,
+            Some(&"hint") => Action::Hint,
+            Some(&"marks") => match parts.get(2) {
+                Some(&"on") => Action::QuestionMarks(true),
+                Some(&"off") => Action::QuestionMarks(false),
+                _ => return Err(invalid(format!("Invalid replay input '{}'!", line))),
+            },
+            _ => return Err(invalid(format!("Invalid replay input '{}'!", line))),
+        };
+        inputs.push(Input {
+            time: Duration::from_millis(number(0)?),
+            action,
+        });
+    }
+
+    let start: String = lines.map(|l| format!("{}\n", l)).collect();
+    let game = save::from_str(&start)?;
+    for input in &inputs {
+        if let Action::Reveal(x, y) | Action::Chord(x, y) | Action::Flag(x, y) = input.action {
+            if x >= game.width() || y >= game.height() {
+                return Err(invalid(format!(
+                    "Replay input outside the board: {} {}!",
+                    x, y
+                )));
+            }
+        }
+    }
+
+    Ok(Replay { start, inputs })
+}
+
+/// Feeds the inputs of a replay to a game as time passes.
+pub struct Playback {
+    replay: Replay,
+    next: usize,
+    position: Duration,
+    pub speed: f64,
+    pub paused: bool,
+}
+
+impl Playback {
+    pub fn new(replay: Replay) -> Playback {
+        Playback {
+            replay,
+            next: 0,
+            position: Duration::ZERO,
+            speed: 1.0,
+            paused: false,
+        }
+    }
+
+    pub fn position(&self) -> Duration {
+        self.position
+    }
+
+    pub fn finished(&self) -> bool {
+        self.next >= self.replay.inputs.len()
+    }
+
+    /// Moves the playback forward by `elapsed` real time, scaled by the
+    /// playback speed, and applies every input that became due.
+    pub fn advance(&mut self, game: &mut Game, elapsed: Duration) {
+        if self.paused {
+            return;
+        }
+        self.position += elapsed.mul_f64(self.speed);
+        while let Some(input) = self.replay.inputs.get(self.next) {
+            if input.time > self.position {
+                break;
+            }
+            // Inputs were recorded while playing, so they only fail if the
+            // replay was edited by hand. Those are skipped like the
+            // original game would have ignored them.
+            let _ = apply(game, input.action);
+            self.next += 1;
+        }
+    }
+}
+
+pub fn apply(game: &mut Game, action: Action) -> std::result::Result<(), GameError> {
+    match action {
+        Action::NewGame(seed) => game.new_game_with_seed(seed),
+        Action::Reveal(x, y) => game.reveal_tile(x, y)?,
+        Action::Chord(x, y) => game.chord_tile(x, y)?,
+        Action::Flag(x, y) => game.flag_tile(x, y)?,
+        Action::Undo => {
+            game.undo();
+        }
+        Action::Redo => {
+            game.redo();
+        }
+        Action::Hint => {
+            game.hint();
+        }
+        Action::QuestionMarks(enabled) => game.set_question_marks(enabled),
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::GameState;
+
+    #[test]
+    fn playback_reaches_the_recorded_board() {
+        let mut game = Game::with_seed(16, 16, 40, 11).unwrap();
+        game.flag_tile(0, 0).unwrap();
+        game.reveal_tile(8, 8).unwrap();
+        game.hint();
+        game.set_question_marks(true);
+        for (x, y) in [(2, 13), (13, 2), (0, 15), (15, 15)] {
+            let _ = game.reveal_tile(x, y);
+        }
+        game.undo();
+        game.redo();
+        game.undo();
+        let _ = game.chord_tile(8, 8);
+
+        let replay = from_str(&to_string(&game.replay())).unwrap();
+        let recorded = game.replay();
+        assert_eq!(replay.inputs().len(), recorded.inputs().len());
+        for (read, written) in replay.inputs().iter().zip(recorded.inputs()) {
+            assert_eq!(read.action, written.action);
+            assert_eq!(read.time.as_millis(), written.time.as_millis());
+        }
+
+        let mut played = replay.start();
+        let mut playback = Playback::new(replay);
+        playback.advance(&mut played, Duration::from_secs(3600));
+        assert!(playback.finished());
+        assert_eq!(played.get_field(), game.get_field());
+        assert_eq!(played.hints_used(), game.hints_used());
+        assert_eq!(played.question_marks(), game.question_marks());
+        assert_eq!(played.assisted(), game.assisted());
+        assert_eq!(
+            matches!(played.state(), GameState::Boom(_)),
+            matches!(game.state(), GameState::Boom(_))
+        );
+    }
+}
@@ -0,0 +1,175 @@
+//! Keeping the results of finished games, shared by the frontends. Every game
+//! that ends saves its replay, counts once in the statistics and may make the
+//! best times.
+
+use std::io::Result;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::field::{Game, GameState};
+use crate::replay;
+use crate::scores::{self, Board, Score};
+use crate::stats::{self, BoardStats};
+
+/// A new best time waiting for the player to enter a name.
+pub struct Record {
+    pub board: Board,
+    pub time: Duration,
+    pub name: String,
+}
+
+/// What was saved when a game ended, for the frontend to report.
+pub struct Ended {
+    /// Where the replay was written.
+    pub replay: Result<PathBuf>,
+    /// Also `Ok` if the game was already counted before.
+    pub stats: Result<()>,
+    /// A win without hints or undo that makes the best times.
+    pub record: Result<Option<Record>>,
+}
+
+pub struct Results {
+    pub stats_file: PathBuf,
+    pub scores_file: PathBuf,
+    pub replay_dir: PathBuf,
+    finished: bool,
+    counted: bool,
+}
+
+impl Default for Results {
+    /// Files in the working directory.
+    fn default() -> Results {
+        Results {
+            stats_file: PathBuf::from("minesweeper.stats"),
+            scores_file: PathBuf::from("minesweeper.scores"),
+            replay_dir: PathBuf::from("replays"),
+            finished: false,
+            counted: false,
+        }
+    }
+}
+
+impl Results {
+    /// Forgets the previous game. Call whenever a new game starts.
+    pub fn new_game(&mut self) {
+        self.finished = false;
+        self.counted = false;
+    }
+
+    /// Games that were already over when saved are not counted again.
+    pub fn loaded(&mut self, game: &Game) {
+        self.finished = !matches!(game.state(), GameState::Playing(_));
+        self.counted = self.finished;
+    }
+
+    /// Call after every input. When the game has just ended, saves the
+    /// replay and the statistics and checks the best times. A game finished
+    /// again after undo gets another replay but is only counted once.
+    pub fn update(&mut self, game: &Game) -> Option<Ended> {
+        let finished = !matches!(game.state(), GameState::Playing(_));
+        let ended = finished && !self.finished;
+        self.finished = finished;
+        if !ended {
+            return None;
+        }
+
+        let stats = if self.counted {
+            Ok(())
+        } else {
+            self.counted = true;
+            self.record_stats(game)
+        };
+        Some(Ended {
+            replay: self.save_replay(game),
+            stats,
+            record: self.check_record(game),
+        })
+    }
+
+    fn record_stats(&self, game: &Game) -> Result<()> {
+        let board = (game.width(), game.height(), game.mine_count());
+        let mut stats = stats::load(&self.stats_file)?;
+        stats.record(board, game.state());
+        stats::save(&stats, &self.stats_file)
+    }
+
+    pub fn stats(&self, board: Board) -> Result<BoardStats> {
+        Ok(stats::load(&self.stats_file)?.get(board))
+    }
+
+    fn check_record(&self, game: &Game) -> Result<Option<Record>> {
+        let time = match game.state() {
+            GameState::Victory(t) => *t,
+            _ => return Ok(None),
+        };
+        if game.hints_used() > 0 || game.assisted() {
+            return Ok(None);
+        }
+        let board = (game.width(), game.height(), game.mine_count());
+        let table = scores::load(&self.scores_file)?;
+        Ok(table.qualifies(board, time).then(|| Record {
+            board,
+            time,
+            name: String::new(),
+        }))
+    }
+
+    pub fn save_record(&self, record: &Record) -> Result<()> {
+        let mut table = scores::load(&self.scores_file)?;
+        table.insert(record.board, Score::new(&record.name, record.time));
+        scores::save(&table, &self.scores_file)
+    }
+
+    fn save_replay(&self, game: &Game) -> Result<PathBuf> {
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs());
+        let path = self
+            .replay_dir
+            .join(format!("{}-{}.replay", time, game.seed()));
+        std::fs::create_dir_all(&self.replay_dir)?;
+        replay::save(&game.replay(), &path)?;
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::TileContent;
+
+    #[test]
+    fn counts_a_game_once() {
+        let dir = std::env::temp_dir().join(format!("minesweeper-results-{}", std::process::id()));
+        let results = &mut Results {
+            stats_file: dir.join("stats"),
+            scores_file: dir.join("scores"),
+            replay_dir: dir.join("replays"),
+            ..Results::default()
+        };
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let mut game = Game::with_seed(9, 9, 10, 5).unwrap();
+        game.reveal_tile(4, 4).unwrap();
+        assert!(results.update(&game).is_none());
+        let (x, y) = (0..81)
+            .map(|i| (i % 9, i / 9))
+            .find(|&(x, y)| matches!(game.get_field()[x][y].content, TileContent::Bomb))
+            .unwrap();
+        // Lose, undo and lose again.
+        for _ in 0..2 {
+            game.reveal_tile(x, y).unwrap();
+            let ended = results.update(&game).unwrap();
+            assert!(ended.replay.unwrap().exists());
+            ended.stats.unwrap();
+            assert!(ended.record.unwrap().is_none());
+            assert!(results.update(&game).is_none());
+            game.undo();
+            assert!(results.update(&game).is_none());
+        }
+        let stats = results.stats((9, 9, 10)).unwrap();
+        assert_eq!((stats.played, stats.won), (1, 0));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,387 @@
+//! Saving games in progress to disk and loading them back.
+//!
+//! A save file is a [text file](crate::format) with one `key value` line per
+//! setting after the header and then the board:
+//!
+//! ```text
+//! minesweeper-save 5
+//! size 9 9
+//! mines 10
+//! seed 1234567890
+//! first-click opening
+//! attempts 1
+//! guess-free 0
+//! state playing
+//! elapsed 15250
+//! hints 0
+//! assisted 0
+//! clicks 12 3 1
+//! marks 0
+//! field
+//! .........
+//! ..o*.....
+//! ...
+//! ```
+//!
+//! * `size` is the width and height in tiles and `mines` the mine count.
+//! * `seed` is the seed the board was generated from.
+//! * `first-click` is the first click policy: `none`, `safe`, `opening` or
+//!   `no-guess`. `attempts` is the number of boards generated on the first
+//!   click. Files before version 3 have `mode random` for `opening` and
+//!   `mode no-guess` for `no-guess` instead.
+//! * `guess-free` is 1 if the board was proven to be clearable without
+//!   guessing. Files before version 5 do not have it; their `no-guess` boards
+//!   load as proven once the first click was made.
+//! * `state` is `new` before the first click, then `playing`, `victory` or
+//!   `boom`. `elapsed` is the play time in milliseconds.
+//! * `hints` is the number of hints used and `assisted` is 1 if undo was used.
+//! * `clicks` is the number of left, right and chord clicks so far. Version 1
+//!   files have no clicks and load with all three at 0.
+//! * `marks` is 1 if marking a tile cycles through flag and question mark.
+//! * `field` is followed by one line per board row, top to bottom, with one
+//!   character per tile, left to right:
+//!
+//! | Tile                 | Safe | Mine |
+//! |----------------------|------|------|
+//! | hidden               | `.`  | `*`  |
+//! | flagged              | `f`  | `F`  |
+//! | question mark        | `q`  | `Q`  |
+//! | revealed             | `o`  | `X`  |
+//! | revealed and flagged | `g`  | `G`  |
+//!
+//! Numbers are not stored, they are recalculated from the mines. The undo
+//! history is not saved.
+
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::field::{
+    tile_count, Clicks, Clock, FirstClickPolicy, Game, GameState, Tile, TileContent,
+};
+use crate::format;
+
+const HEADER: &str = "minesweeper-save";
+const VERSION: u32 = 5;
+const TILES: [(char, bool, bool, bool, bool); 10] = [
+    // (symbol, mine, revealed, flagged, questioned)
+    ('.', false, false, false, false),
+    ('*', true, false, false, false),
+    ('f', false, false, true, false),
+    ('F', true, false, true, false),
+    ('q', false, false, false, true),
+    ('Q', true, false, false, true),
+    ('o', false, true, false, false),
+    ('X', true, true, false, false),
+    ('g', false, true, true, false),
+    ('G', true, true, true, false),
+];
+
+pub(crate) struct SavedGame {
+    pub mine_count: i32,
+    pub seed: u64,
+    pub first_click: FirstClickPolicy,
+    pub question_marks: bool,
+    pub generation_attempts: u32,
+    pub guess_free: bool,
+    pub hints_used: u32,
+    pub clicks: Clicks,
+    pub assisted: bool,
+    pub state: GameState,
+    pub field: Vec<Vec<Tile>>,
+}
+
+pub fn save(game: &Game, path: &Path) -> Result<()> {
+    fs::write(path, to_string(game)?)
+}
+
+pub fn load(path: &Path) -> Result<Game> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+/// Fails if a tile has a combination of marks the format has no symbol for.
+pub fn to_string(game: &Game) -> Result<String> {
+    let saved = game.to_saved();
+    let (state, elapsed) = match saved.state {
+        GameState::Playing(None) => ("new", Duration::ZERO),
+        GameState::Playing(Some(clock)) => ("playing", clock.elapsed()),
+        GameState::Victory(t) => ("victory", t),
+        GameState::Boom(t) => ("boom", t),
+    };
+
+    let mut text = format!("{} {}\n", HEADER, VERSION);
+    text += &format!("size {} {}\n", game.width(), game.height());
+    text += &format!("mines {}\n", saved.mine_count);
+    text += &format!("seed {}\n", saved.seed);
+    text += &format!("first-click {}\n", saved.first_click.name());
+    text += &format!("attempts {}\n", saved.generation_attempts);
+    text += &format!("guess-free {}\n", saved.guess_free as u8);
+    text += &format!("state {}\n", state);
+    text += &format!("elapsed {}\n", elapsed.as_millis());
+    text += &format!("hints {}\n", saved.hints_used);
+    text += &format!("assisted {}\n", saved.assisted as u8);
+    text += &format!(
+        "clicks {} {} {}\n",
+        saved.clicks.left, saved.clicks.right, saved.clicks.chord
+    );
+    text += &format!("marks {}\n", saved.question_marks as u8);
+    text += "field\n";
+    for y in 0..game.height() {
+        for (x, column) in saved.field.iter().enumerate() {
+            let tile = &column[y];
+            let mine = matches!(tile.content, TileContent::Bomb);
+            let marks = (mine, tile.revealed, tile.flagged, tile.questioned);
+            let (symbol, ..) = TILES
+                .iter()
+                .find(|(_, m, r, f, q)| (*m, *r, *f, *q) == marks)
+                .ok_or_else(|| invalid(format!("Tile {} {} cannot be saved!", x, y)))?;
+            text.push(*symbol);
+        }
+        text.push('\n');
+    }
+    Ok(text)
+}
+
+fn invalid(message: String) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+fn parse<T: std::str::FromStr>(key: &str, value: Option<&str>) -> Result<T> {
+    value
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| invalid(format!("Invalid value for '{}' in save file!", key)))
+}
+
+pub fn from_str(text: &str) -> Result<Game> {
+    let mut lines = text.lines();
+    format::check_header(&mut lines, HEADER, 1..=VERSION, "save")?;
+
+    let (mut w, mut h, mut mine_count, mut seed) = (0usize, 0usize, 0, 0);
+    let (mut first_click, mut attempts, mut guess_free) = (FirstClickPolicy::Opening, 0, None);
+    let (mut hints, mut assisted, mut question_marks) = (0, false, false);
+    let (mut state, mut elapsed) = (String::new(), 0u64);
+    let mut clicks = Clicks::default();
+    for line in lines.by_ref() {
+        if line == "field" {
+            break;
+        }
+        let mut parts = line.split_whitespace();
+        let key = parts.next().unwrap_or_default();
+        match key {
+            "size" => {
+                w = parse(key, parts.next())?;
+                h = parse(key, parts.next())?;
+            }
+            "mines" => mine_count = parse(key, parts.next())?,
+            "seed" => seed = parse(key, parts.next())?,
+            "first-click" => {
+                first_click = parts
+                    .next()
+                    .and_then(FirstClickPolicy::from_name)
+                    .ok_or_else(|| invalid(format!("Invalid value for '{}' in save file!", key)))?
+            }
+            "mode" => {
+                first_click = match parts.next() {
+                    Some("random") => FirstClickPolicy::Opening,
+                    Some("no-guess") => FirstClickPolicy::NoGuess,
+                    _ => return Err(invalid("Invalid value for 'mode' in save file!".into())),
+                }
+            }
+            "attempts" => attempts = parse(key, parts.next())?,
+            "guess-free" => guess_free = Some(parse::<u8>(key, parts.next())? != 0),
+            "state" => state = parse(key, parts.next())?,
+            "elapsed" => elapsed = parse(key, parts.next())?,
+            "hints" => hints = parse(key, parts.next())?,
+            "assisted" => assisted = parse::<u8>(key, parts.next())? != 0,
+            "clicks" => {
+                clicks.left = parse(key, parts.next())?;
+                clicks.right = parse(key, parts.next())?;
+                clicks.chord = parse(key, parts.next())?;
+            }
+            "marks" => question_marks = parse::<u8>(key, parts.next())? != 0,
+            _ => return Err(invalid(format!("Unknown key '{}' in save file!", key))),
+        }
+    }
+    if w == 0 || h == 0 {
+        return Err(invalid("Missing board size in save file!".to_string()));
+    }
+    if tile_count(w, h).is_none() {
+        return Err(invalid(format!(
+            "Board of {}x{} in save file is too large!",
+            w, h
+        )));
+    }
+
+    let elapsed = Duration::from_millis(elapsed);
+    let state = match state.as_str() {
+        "new" => GameState::Playing(None),
+        "playing" => GameState::Playing(Some(Clock::resume(elapsed))),
+        "victory" => GameState::Victory(elapsed),
+        "boom" => GameState::Boom(elapsed),
+        _ => return Err(invalid(format!("Invalid game state '{}'!", state))),
+    };
+
+    let mut field: Vec<Vec<Tile>> = vec![vec![]; w];
+    let mut mines = 0;
+    for y in 0..h {
+        let row: Vec<char> = lines.next().unwrap_or_default().chars().collect();
+        if row.len() != w {
+            return Err(invalid(format!("Row {} of the field has wrong length!", y)));
+        }
+        for (x, symbol) in row.into_iter().enumerate() {
+            let (_, mine, revealed, flagged, questioned) = *TILES
+                .iter()
+                .find(|(s, ..)| *s == symbol)
+                .ok_or_else(|| invalid(format!("Invalid tile '{}' in save file!", symbol)))?;
+            mines += mine as i32;
+            field[x].push(Tile {
+                content: if mine {
+                    TileContent::Bomb
+                } else {
+                    TileContent::Empty(0)
+                },
+                revealed,
+                flagged,
+                questioned,
+            });
+        }
+    }
+    if mines != mine_count {
+        return Err(invalid(format!(
+            "Save file has {} mines on the field but mine count {}!",
+            mines, mine_count
+        )));
+    }
+
+    Game::from_saved(SavedGame {
+        mine_count,
+        seed,
+        first_click,
+        question_marks,
+        generation_attempts: attempts,
+        guess_free: guess_free.unwrap_or(first_click == FirstClickPolicy::NoGuess && attempts > 0),
+        hints_used: hints,
+        clicks,
+        assisted,
+        state,
+        field,
+    })
+    .map_err(|err| invalid(err.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELD: &str = "\
+*o......*
+oo.......
+.....*...
+...*.....
+....F....
+.........
+..*...*..
+.........
+*...*...*
+";
+
+    fn without_elapsed(text: &str) -> String {
+        text.lines()
+            .filter(|l| !l.starts_with("elapsed"))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut game = Game::with_seed(30, 16, 99, 3).unwrap();
+        game.set_question_marks(true);
+        game.reveal_tile(15, 8).unwrap();
+        let hidden = (0..30)
+            .flat_map(|x| (0..16).map(move |y| (x, y)))
+            .filter(|&(x, y)| !game.get_field()[x][y].revealed)
+            .take(2)
+            .collect::<Vec<_>>();
+        game.flag_tile(hidden[0].0, hidden[0].1).unwrap();
+        game.flag_tile(hidden[1].0, hidden[1].1).unwrap();
+        game.flag_tile(hidden[1].0, hidden[1].1).unwrap();
+
+        let text = to_string(&game).unwrap();
+        let loaded = from_str(&text).unwrap();
+        assert_eq!(loaded.get_field(), game.get_field());
+        assert_eq!(loaded.mines_remaining(), game.mines_remaining());
+        assert_eq!(loaded.clicks(), game.clicks());
+        assert_eq!(
+            without_elapsed(&to_string(&loaded).unwrap()),
+            without_elapsed(&text)
+        );
+    }
+
+    #[test]
+    fn saves_won_game_with_question_mark_on_mine() {
+        let text = "minesweeper-save 5\nsize 3 1\nmines 1\nfirst-click none\nstate playing\n\
+                    marks 1\nfield\nQo.\n";
+        let mut game = from_str(text).unwrap();
+        game.reveal_tile(2, 0).unwrap();
+        assert!(matches!(game.state(), GameState::Victory(_)));
+
+        let loaded = from_str(&to_string(&game).unwrap()).unwrap();
+        assert_eq!(loaded.get_field(), game.get_field());
+        let mine = &loaded.get_field()[0][0];
+        assert!(mine.revealed && mine.flagged && !mine.questioned);
+    }
+
+    #[test]
+    fn loads_older_versions() {
+        let v1 = "minesweeper-save 1\nsize 9 9\nmines 10\nseed 7\nmode no-guess\nattempts 4\n\
+                  state playing\nelapsed 15250\nhints 2\nassisted 1\nfield\n";
+        let game = from_str(&(v1.to_string() + FIELD)).unwrap();
+        assert_eq!(game.first_click_policy(), FirstClickPolicy::NoGuess);
+        assert_eq!(game.generation_attempts(), 4);
+        assert!(game.guess_free());
+        assert_eq!(game.hints_used(), 2);
+        assert!(game.assisted());
+        assert_eq!(game.clicks(), Clicks::default());
+        assert_eq!(game.mines_remaining(), 9);
+        assert!(game.elapsed() >= Duration::from_millis(15250));
+        assert!(matches!(
+            game.get_field()[0][1].content,
+            TileContent::Empty(1)
+        ));
+
+        let v2 = "minesweeper-save 2\nsize 9 9\nmines 10\nseed 7\nmode random\nattempts 1\n\
+                  state victory\nelapsed 15250\nhints 0\nassisted 0\nclicks 12 3 1\nfield\n";
+        let game = from_str(&(v2.to_string() + FIELD)).unwrap();
+        assert_eq!(game.first_click_policy(), FirstClickPolicy::Opening);
+        assert_eq!(game.clicks().total(), 16);
+        assert!(matches!(game.state(), GameState::Victory(_)));
+
+        let v3 = "minesweeper-save 3\nsize 9 9\nmines 10\nseed 7\nfirst-click safe\nattempts 1\n\
+                  state boom\nelapsed 15250\nhints 0\nassisted 0\nclicks 12 3 1\nfield\n";
+        let game = from_str(&(v3.to_string() + FIELD)).unwrap();
+        assert_eq!(game.first_click_policy(), FirstClickPolicy::SafeCell);
+        assert!(!game.question_marks());
+        assert!(matches!(game.state(), GameState::Boom(_)));
+    }
+
+    #[test]
+    fn rejects_invalid_files() {
+        let v3 = "minesweeper-save 3\nsize 8 8\nmines 60\nseed 7\nfirst-click opening\n\
+                  state new\nelapsed 0\nfield\n";
+        let field = "*".repeat(60) + &".".repeat(4);
+        let rows: String = field
+            .as_bytes()
+            .chunks(8)
+            .map(|row| String::from_utf8_lossy(row).to_string() + "\n")
+            .collect();
+        assert!(from_str(&(v3.to_string() + &rows)).is_err());
+        assert!(from_str(&(v3.replace("opening", "none") + &rows)).is_ok());
+        assert!(from_str(&v3.replace("save 3", "save 99")).is_err());
+        assert!(from_str("minesweeper-scores 1\n").is_err());
+        let huge =
+            "minesweeper-save 5\nsize 18446744073709551615 1\nmines 0\nstate new\nfield\n.\n";
+        let err = from_str(huge).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,273 @@
+//! Best times for every board size and mine count.
+//!
+//! The score file is a [text file](crate::format) with a `board` line for
+//! every table after the header, each followed by one line per score, best
+//! first:
+//!
+//! ```text
+//! minesweeper-scores 1
+//! board 9 9 10
+//! 8731 1760000000 Alice
+//! 12045 1760003600 Bob Smith
+//! board 30 16 99
+//! 95310 1760007200 Alice
+//! ```
+//!
+//! `board` is followed by the width, height and mine count. A score line holds
+//! the time in milliseconds, the date as seconds since the Unix epoch and the
+//! player name, which runs to the end of the line.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::format;
+
+const HEADER: &str = "minesweeper-scores";
+const VERSION: u32 = 1;
+/// Number of times kept for every board.
+pub const TOP_SCORES: usize = 10;
+pub const MAX_NAME_LENGTH: usize = 24;
+
+/// Width, height and mine count.
+pub type Board = (usize, usize, i32);
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Score {
+    pub name: String,
+    pub time: Duration,
+    /// Seconds since the Unix epoch.
+    pub date: u64,
+}
+
+impl Score {
+    /// A score set now. Line breaks are removed from the name and it is cut to
+    /// [`MAX_NAME_LENGTH`] characters.
+    pub fn new(name: &str, time: Duration) -> Score {
+        let name: String = name
+            .chars()
+            .filter(|c| !c.is_control())
+            .take(MAX_NAME_LENGTH)
+            .collect();
+        let name = match name.trim() {
+            "" => "Anonymous".to_string(),
+            name => name.to_string(),
+        };
+        let date = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |t| t.as_secs());
+        Score { name, time, date }
+    }
+
+    /// The date as `YYYY-MM-DD` in UTC.
+    pub fn date_string(&self) -> String {
+        // Converts days since the epoch to a civil date, see
+        // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+        let z = (self.date / 86400) as i64 + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z - era * 146097;
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i64;
+        format!("{:04}-{:02}-{:02}", year, month, day)
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ScoreTable {
+    boards: BTreeMap<Board, Vec<Score>>,
+}
+
+impl ScoreTable {
+    pub fn new() -> ScoreTable {
+        ScoreTable::default()
+    }
+
+    pub fn boards(&self) -> impl Iterator<Item = (&Board, &Vec<Score>)> {
+        self.boards.iter()
+    }
+
+    pub fn scores(&self, board: Board) -> &[Score] {
+        self.boards.get(&board).map_or(&[], |s| s.as_slice())
+    }
+
+    /// Whether `time` would make it into the table of `board`.
+    pub fn qualifies(&self, board: Board, time: Duration) -> bool {
+        let scores = self.scores(board);
+        scores.len() < TOP_SCORES || scores.iter().any(|s| time < s.time)
+    }
+
+    /// Adds a score and returns its rank starting from 0, or `None` when it is
+    /// not fast enough. Equal times keep the older score first.
+    pub fn insert(&mut self, board: Board, score: Score) -> Option<usize> {
+        let scores = self.boards.entry(board).or_default();
+        let rank = scores.iter().take_while(|s| s.time <= score.time).count();
+        if rank >= TOP_SCORES {
+            return None;
+        }
+        scores.insert(rank, score);
+        scores.truncate(TOP_SCORES);
+        Some(rank)
+    }
+}
+
+pub fn save(table: &ScoreTable, path: &Path) -> Result<()> {
+    fs::write(path, to_string(table))
+}
+
+/// Loads the table, or returns an empty one if the file does not exist yet.
+pub fn load(path: &Path) -> Result<ScoreTable> {
+    match fs::read_to_string(path) {
+        Ok(text) => from_str(&text),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(ScoreTable::new()),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn to_string(table: &ScoreTable) -> String {
+    let mut text = format!("{} {}\n", HEADER, VERSION);
+    for ((w, h, mine_count), scores) in &table.boards {
+        text += &format!("board {} {} {}\n", w, h, mine_count);
+        for score in scores {
+            text += &format!("{} {} {}\n", score.time.as_millis(), score.date, score.name);
+        }
+    }
+    text
+}
+
+pub fn from_str(text: &str) -> Result<ScoreTable> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+
+    let mut lines = text.lines();
+    format::check_header(&mut lines, HEADER, 1..=VERSION, "scores")?;
+
+    let mut table = ScoreTable::new();
+    let mut board = None;
+    for line in lines {
+        let error = || invalid(format!("Invalid line '{}' in scores file!", line));
+        if let Some(size) = line.strip_prefix("board ") {
+            let numbers: Vec<usize> = size
+                .split_whitespace()
+                .map(|n| n.parse().map_err(|_| error()))
+                .collect::<Result<_>>()?;
+            board = match numbers.as_slice() {
+                &[w, h, mine_count] => Some((w, h, mine_count as i32)),
+                _ => return Err(error()),
+            };
+            continue;
+        }
+
+        let board = board.ok_or_else(error)?;
+        let parts: Vec<&str> = line.splitn(3, ' ').collect();
+        let (time, date, name) = match parts.as_slice() {
+            [time, date, name] => (time, date, name),
+            _ => return Err(error()),
+        };
+        let score = Score {
+            name: name.to_string(),
+            time: Duration::from_millis(time.parse().map_err(|_| error())?),
+            date: date.parse().map_err(|_| error())?,
+        };
+        table.insert(board, score);
+    }
+    Ok(table)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BOARD: Board = (9, 9, 10);
+
+    fn score(name: &str, millis: u64) -> Score {
+        Score {
+            name: name.to_string(),
+            time: Duration::from_millis(millis),
+            date: 1760000000,
+        }
+    }
+
+    fn times(table: &ScoreTable) -> Vec<u64> {
+        table
+            .scores(BOARD)
+            .iter()
+            .map(|s| s.time.as_millis() as u64)
+            .collect()
+    }
+
+    #[test]
+    fn insert_keeps_best_first() {
+        let mut table = ScoreTable::new();
+        assert_eq!(table.insert(BOARD, score("first", 20_000)), Some(0));
+        assert_eq!(table.insert(BOARD, score("fast", 10_000)), Some(0));
+        assert_eq!(table.insert(BOARD, score("second", 20_000)), Some(2));
+        let names: Vec<&str> = table.scores(BOARD).iter().map(|s| &*s.name).collect();
+        assert_eq!(names, ["fast", "first", "second"]);
+        assert!(table.scores((16, 16, 40)).is_empty());
+    }
+
+    #[test]
+    fn full_table() {
+        let mut table = ScoreTable::new();
+        for secs in (1..=TOP_SCORES as u64 + 2).rev() {
+            table.insert(BOARD, score("player", secs * 1000));
+        }
+        assert_eq!(
+            times(&table),
+            (1..=10).map(|s| s * 1000).collect::<Vec<_>>()
+        );
+
+        assert!(!table.qualifies(BOARD, Duration::from_millis(11_000)));
+        assert!(!table.qualifies(BOARD, Duration::from_millis(10_000)));
+        assert!(table.qualifies(BOARD, Duration::from_millis(9_999)));
+        assert!(table.qualifies((16, 16, 40), Duration::from_secs(999)));
+
+        assert_eq!(table.insert(BOARD, score("slow", 10_000)), None);
+        assert_eq!(table.insert(BOARD, score("fast", 9_999)), Some(9));
+        assert_eq!(table.scores(BOARD).len(), TOP_SCORES);
+        assert_eq!(table.scores(BOARD)[9].name, "fast");
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut table = ScoreTable::new();
+        table.insert(BOARD, score("Alice", 8731));
+        table.insert(BOARD, score("Bob  Smith Jr.", 12045));
+        table.insert((30, 16, 99), score("Alice", 95310));
+
+        let text = to_string(&table);
+        let loaded = from_str(&text).unwrap();
+        assert_eq!(loaded.scores(BOARD), table.scores(BOARD));
+        assert_eq!(loaded.scores((30, 16, 99)), table.scores((30, 16, 99)));
+        assert_eq!(to_string(&loaded), text);
+        assert!(from_str("minesweeper-scores 1\n8731 1760000000 Alice\n").is_err());
+    }
+
+    #[test]
+    fn names_are_cleaned() {
+        let name = Score::new(" Line\nbreak ", Duration::ZERO).name;
+        assert_eq!(name, "Linebreak");
+        assert_eq!(Score::new(" \t", Duration::ZERO).name, "Anonymous");
+        let long = Score::new(&"x".repeat(40), Duration::ZERO).name;
+        assert_eq!(long.len(), MAX_NAME_LENGTH);
+    }
+
+    #[test]
+    fn date_string() {
+        let date = |date| {
+            Score {
+                date,
+                ..score("", 0)
+            }
+            .date_string()
+        };
+        assert_eq!(date(0), "1970-01-01");
+        assert_eq!(date(1760000000), "2025-10-09");
+        assert_eq!(date(951782400), "2000-02-29");
+    }
+}
@@ -0,0 +1,263 @@
+//! Logical solver that works only on what a player can see: revealed
+//! numbers, flags and the total mine count.
+
+use crate::field::{Game, Tile, TileContent};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Cell {
+    Hidden,
+    Flagged,
+    Number(i32),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Rule {
+    /// A number already has all of its mines flagged, or needs every one of
+    /// its hidden neighbours to be a mine.
+    SingleCell,
+    /// Comparing two overlapping numbers forces the tiles only one of them
+    /// touches.
+    Subset,
+    /// The number of mines left on the board forces the remaining tiles.
+    GlobalCount,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Deduction {
+    pub x: usize,
+    pub y: usize,
+    pub mine: bool,
+    pub rule: Rule,
+}
+
+pub(crate) struct Constraint {
+    pub cells: Vec<(usize, usize)>,
+    pub mines: i32,
+}
+
+// Everything below works on `Cell`s, so hidden tile contents are never read.
+pub(crate) fn visible_cells(field: &[Vec<Tile>]) -> Vec<Vec<Cell>> {
+    field
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|tile| {
+                    if tile.flagged {
+                        Cell::Flagged
+                    } else if !tile.revealed {
+                        Cell::Hidden
+                    } else {
+                        match tile.content {
+                            TileContent::Empty(n) => Cell::Number(n),
+                            TileContent::Bomb => Cell::Flagged,
+                        }
+                    }
+                })
+                .collect()
+        })
+        .collect()
+}
+
+pub(crate) fn constraints(cells: &[Vec<Cell>]) -> Vec<Constraint> {
+    let (w, h) = (cells.len(), cells[0].len());
+    let mut result = vec![];
+
+    for x in 0..w {
+        for y in 0..h {
+            if let Cell::Number(n) = cells[x][y] {
+                let mut hidden = vec![];
+                let mut flags = 0;
+                Game::do_for_neightbors(
+                    x,
+                    y,
+                    |x, y| match cells[x][y] {
+                        Cell::Hidden => hidden.push((x, y)),
+                        Cell::Flagged => flags += 1,
+                        Cell::Number(_) => {}
+                    },
+                    w,
+                    h,
+                );
+                if !hidden.is_empty() {
+                    hidden.sort();
+                    result.push(Constraint {
+                        cells: hidden,
+                        mines: n - flags,
+                    });
+                }
+            }
+        }
+    }
+
+    result
+}
+
+fn add(found: &mut Vec<Deduction>, cells: &[(usize, usize)], mine: bool, rule: Rule) {
+    for &(x, y) in cells {
+        found.push(Deduction { x, y, mine, rule });
+    }
+}
+
+fn single_cell(constraints: &[Constraint], found: &mut Vec<Deduction>) {
+    for c in constraints {
+        if c.mines == 0 {
+            add(found, &c.cells, false, Rule::SingleCell);
+        } else if c.mines == c.cells.len() as i32 {
+            add(found, &c.cells, true, Rule::SingleCell);
+        }
+    }
+}
+
+fn pairs(constraints: &[Constraint], w: usize, h: usize, found: &mut Vec<Deduction>) {
+    let mut touching = vec![vec![vec![]; h]; w];
+    for (i, c) in constraints.iter().enumerate() {
+        for &(x, y) in &c.cells {
+            touching[x][y].push(i);
+        }
+    }
+
+    for a in constraints {
+        let mut partners: Vec<usize> = a
+            .cells
+            .iter()
+            .flat_map(|&(x, y)| touching[x][y].iter().copied())
+            .collect();
+        partners.sort();
+        partners.dedup();
+
+        for b in partners.into_iter().map(|i| &constraints[i]) {
+            let only_a = a.cells.iter().filter(|c| !b.cells.contains(c)).count() as i32;
+            let shared = a.cells.len() as i32 - only_a;
+            let only_b: Vec<(usize, usize)> = b
+                .cells
+                .iter()
+                .filter(|c| !a.cells.contains(c))
+                .copied()
+                .collect();
+            if only_b.is_empty() {
+                continue;
+            }
+
+            let most_shared = a.mines.min(shared);
+            let least_shared = (a.mines - only_a).max(0);
+            if b.mines - most_shared == only_b.len() as i32 {
+                add(found, &only_b, true, Rule::Subset);
+            } else if b.mines == least_shared {
+                add(found, &only_b, false, Rule::Subset);
+            }
+        }
+    }
+}
+
+fn global_count(
+    cells: &[Vec<Cell>],
+    constraints: &[Constraint],
+    mine_count: i32,
+    found: &mut Vec<Deduction>,
+) {
+    let hidden: Vec<(usize, usize)> = cells
+        .iter()
+        .enumerate()
+        .flat_map(|(x, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|(_, cell)| **cell == Cell::Hidden)
+                .map(move |(y, _)| (x, y))
+        })
+        .collect();
+    let flags = cells
+        .iter()
+        .flatten()
+        .filter(|c| **c == Cell::Flagged)
+        .count() as i32;
+    let remaining = mine_count - flags;
+
+    if remaining == 0 {
+        add(found, &hidden, false, Rule::GlobalCount);
+        return;
+    }
+    if remaining == hidden.len() as i32 {
+        add(found, &hidden, true, Rule::GlobalCount);
+        return;
+    }
+
+    // Disjoint numbers hold at least this many of the remaining mines between
+    // them. If that already accounts for all of them, every other hidden tile
+    // is safe.
+    let mut order: Vec<&Constraint> = constraints.iter().collect();
+    order.sort_by_key(|c| -c.mines);
+    let mut covered = vec![vec![false; cells[0].len()]; cells.len()];
+    let mut least = 0;
+    for c in order {
+        if c.cells.iter().all(|&(x, y)| !covered[x][y]) {
+            c.cells.iter().for_each(|&(x, y)| covered[x][y] = true);
+            least += c.mines;
+        }
+    }
+    if least == remaining {
+        let rest: Vec<(usize, usize)> = hidden
+            .into_iter()
+            .filter(|&(x, y)| !covered[x][y])
+            .collect();
+        add(found, &rest, false, Rule::GlobalCount);
+    }
+}
+
+/// Returns every tile that can be proven safe or a mine using the simplest
+/// rule that makes progress. Applying the result and calling this again
+/// walks through the solution one step at a time.
+pub fn deduce(game: &Game) -> Vec<Deduction> {
+    let (w, h) = game.size();
+    let cells = visible_cells(game.get_field());
+    let constraints = constraints(&cells);
+
+    let mut found = vec![];
+    single_cell(&constraints, &mut found);
+    if found.is_empty() {
+        pairs(&constraints, w, h, &mut found);
+    }
+    if found.is_empty() {
+        global_count(&cells, &constraints, game.mine_count(), &mut found);
+    }
+
+    found.sort_by_key(|d| (d.x, d.y));
+    found.dedup_by_key(|d| (d.x, d.y));
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::field::GameState;
+
+    #[test]
+    fn deductions_match_the_mines() {
+        let mut steps = 0;
+        for seed in 0..50 {
+            let mut game = Game::with_seed(30, 16, 99, seed).unwrap();
+            game.reveal_tile(15, 8).unwrap();
+            while matches!(game.state(), GameState::Playing(_)) {
+                let deductions = deduce(&game);
+                if deductions.is_empty() {
+                    break;
+                }
+                for d in deductions {
+                    let mine = matches!(game.get_field()[d.x][d.y].content, TileContent::Bomb);
+                    assert_eq!(
+                        d.mine, mine,
+                        "seed {} tile {} {} by {:?}",
+                        seed, d.x, d.y, d.rule
+                    );
+                    if d.mine {
+                        game.flag_tile(d.x, d.y).unwrap();
+                    } else {
+                        game.reveal_tile(d.x, d.y).unwrap();
+                    }
+                    steps += 1;
+                }
+            }
+            assert!(!matches!(game.state(), GameState::Boom(_)), "seed {}", seed);
+        }
+        assert!(steps > 1000);
+    }
+}
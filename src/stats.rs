@@ -0,0 +1,208 @@
+//! Lifetime statistics for every board size and mine count.
+//!
+//! The statistics file is a [text file](crate::format) with one line per
+//! board after the header:
+//!
+//! ```text
+//! minesweeper-stats 1
+//! board 9 9 10 25 14 2 5 189204
+//! ```
+//!
+//! After `board` come the width, height and mine count, then the games played,
+//! games won, current and longest winning streak, and the total time of all
+//! wins in milliseconds.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::time::Duration;
+
+use crate::field::GameState;
+use crate::format;
+use crate::scores::Board;
+
+const HEADER: &str = "minesweeper-stats";
+const VERSION: u32 = 1;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Debug)]
+pub struct BoardStats {
+    pub played: u32,
+    pub won: u32,
+    pub current_streak: u32,
+    pub longest_streak: u32,
+    pub total_win_time: Duration,
+}
+
+impl BoardStats {
+    /// Share of games won, from 0 to 1.
+    pub fn win_rate(&self) -> f64 {
+        if self.played == 0 {
+            0.0
+        } else {
+            self.won as f64 / self.played as f64
+        }
+    }
+
+    pub fn average_win_time(&self) -> Option<Duration> {
+        if self.won == 0 {
+            None
+        } else {
+            Some(self.total_win_time / self.won)
+        }
+    }
+
+    pub fn record_win(&mut self, time: Duration) {
+        self.played += 1;
+        self.won += 1;
+        self.current_streak += 1;
+        self.longest_streak = self.longest_streak.max(self.current_streak);
+        self.total_win_time += time;
+    }
+
+    pub fn record_loss(&mut self) {
+        self.played += 1;
+        self.current_streak = 0;
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct Statistics {
+    boards: BTreeMap<Board, BoardStats>,
+}
+
+impl Statistics {
+    pub fn new() -> Statistics {
+        Statistics::default()
+    }
+
+    pub fn boards(&self) -> impl Iterator<Item = (&Board, &BoardStats)> {
+        self.boards.iter()
+    }
+
+    pub fn get(&self, board: Board) -> BoardStats {
+        self.boards.get(&board).copied().unwrap_or_default()
+    }
+
+    /// Counts a finished game. Games still in progress are ignored.
+    pub fn record(&mut self, board: Board, state: &GameState) {
+        let stats = self.boards.entry(board).or_default();
+        match state {
+            GameState::Victory(t) => stats.record_win(*t),
+            GameState::Boom(_) => stats.record_loss(),
+            GameState::Playing(_) => {}
+        }
+    }
+}
+
+pub fn save(stats: &Statistics, path: &Path) -> Result<()> {
+    fs::write(path, to_string(stats))
+}
+
+/// Loads the statistics, or returns empty ones if the file does not exist yet.
+pub fn load(path: &Path) -> Result<Statistics> {
+    match fs::read_to_string(path) {
+        Ok(text) => from_str(&text),
+        Err(err) if err.kind() == ErrorKind::NotFound => Ok(Statistics::new()),
+        Err(err) => Err(err),
+    }
+}
+
+pub fn to_string(stats: &Statistics) -> String {
+    let mut text = format!("{} {}\n", HEADER, VERSION);
+    for ((w, h, mine_count), s) in &stats.boards {
+        text += &format!(
+            "board {} {} {} {} {} {} {} {}\n",
+            w,
+            h,
+            mine_count,
+            s.played,
+            s.won,
+            s.current_streak,
+            s.longest_streak,
+            s.total_win_time.as_millis()
+        );
+    }
+    text
+}
+
+pub fn from_str(text: &str) -> Result<Statistics> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+
+    let mut lines = text.lines();
+    format::check_header(&mut lines, HEADER, 1..=VERSION, "statistics")?;
+
+    let mut stats = Statistics::new();
+    for line in lines {
+        let error = || invalid(format!("Invalid line '{}' in statistics file!", line));
+        let numbers: Vec<u64> = line
+            .strip_prefix("board ")
+            .ok_or_else(error)?
+            .split_whitespace()
+            .map(|n| n.parse().map_err(|_| error()))
+            .collect::<Result<_>>()?;
+        let (board, s) = match numbers.as_slice() {
+            &[w, h, mine_count, played, won, current, longest, time] => (
+                (w as usize, h as usize, mine_count as i32),
+                BoardStats {
+                    played: played as u32,
+                    won: won as u32,
+                    current_streak: current as u32,
+                    longest_streak: longest as u32,
+                    total_win_time: Duration::from_millis(time),
+                },
+            ),
+            _ => return Err(error()),
+        };
+        stats.boards.insert(board, s);
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn streaks_and_average_time() {
+        let mut s = BoardStats::default();
+        assert_eq!(s.average_win_time(), None);
+        s.record_win(Duration::from_millis(10_000));
+        s.record_win(Duration::from_millis(20_000));
+        s.record_loss();
+        assert_eq!(s.current_streak, 0);
+        assert_eq!(s.longest_streak, 2);
+        s.record_win(Duration::from_millis(30_500));
+        assert_eq!((s.played, s.won), (4, 3));
+        assert_eq!((s.current_streak, s.longest_streak), (1, 2));
+        assert_eq!(s.win_rate(), 0.75);
+        assert_eq!(
+            s.average_win_time(),
+            Some(Duration::from_millis(60_500) / 3)
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut stats = Statistics::new();
+        stats.record(
+            (9, 9, 10),
+            &GameState::Victory(Duration::from_millis(15_250)),
+        );
+        stats.record((9, 9, 10), &GameState::Boom(Duration::ZERO));
+        stats.record(
+            (30, 16, 99),
+            &GameState::Victory(Duration::from_millis(99_001)),
+        );
+        stats.record((30, 16, 99), &GameState::Playing(None));
+
+        let text = to_string(&stats);
+        let loaded = from_str(&text).unwrap();
+        assert_eq!(to_string(&loaded), text);
+        for (board, s) in stats.boards() {
+            assert_eq!(loaded.get(*board), *s);
+        }
+        assert_eq!(loaded.get((30, 16, 99)).played, 1);
+        assert!(from_str("minesweeper-stats 1\nboard 9 9 10\n").is_err());
+    }
+}
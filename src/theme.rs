@@ -0,0 +1,281 @@
+//! Sprite sheet layouts for skinning the board.
+//!
+//! A theme manifest is a [text file](crate::format) with settings, the sprite
+//! sheets and one line per sprite after the header:
+//!
+//! ```text
+//! minesweeper-theme 1
+//! name classic
+//! tile-size 16
+//! counters 9 9 13
+//! face-y 7
+//! sheet tiles tiles.bmp
+//! sheet borders borders.bmp
+//! ...
+//! hidden tiles 0 36 16 16
+//! border-top-left borders 0 0 52 40
+//! ...
+//! ```
+//!
+//! * `tile-size` is the width and height of a tile in pixels.
+//! * `counters` is the position of the first digit of the mine counter and
+//!   the distance between digits. The timer mirrors it on the right.
+//! * `face-y` is the top of the face, which is centered horizontally.
+//! * `sheet` names a sprite sheet and gives its BMP file, relative to the
+//!   manifest.
+//! * Every other line places a sprite: its name, the sheet, then the
+//!   rectangle's left, top, width and height. All sprites in [`SPRITES`]
+//!   must be given.
+//!
+//! Tile sprites must be `tile-size` square. The header height and the border
+//! widths come from the border sprites: the header is as tall as
+//! `border-top-left`, the sides as wide as `border-left` and `border-right`
+//! and the bottom as tall as `border-bottom`.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+
+use crate::format;
+
+const HEADER: &str = "minesweeper-theme";
+const VERSION: u32 = 1;
+const CLASSIC: &str = include_str!("../assets/classic.theme");
+
+/// Names of all sprites a manifest must place.
+pub const SPRITES: [&str; 39] = [
+    "hidden",
+    "question",
+    "pressed",
+    "mine",
+    "exploded",
+    "flag",
+    "wrong-flag",
+    "tile-0",
+    "tile-1",
+    "tile-2",
+    "tile-3",
+    "tile-4",
+    "tile-5",
+    "tile-6",
+    "tile-7",
+    "tile-8",
+    "digit-0",
+    "digit-1",
+    "digit-2",
+    "digit-3",
+    "digit-4",
+    "digit-5",
+    "digit-6",
+    "digit-7",
+    "digit-8",
+    "digit-9",
+    "face",
+    "face-pressed",
+    "face-won",
+    "face-lost",
+    "border-top-left",
+    "border-top",
+    "border-top-middle",
+    "border-top-right",
+    "border-left",
+    "border-right",
+    "border-bottom-left",
+    "border-bottom",
+    "border-bottom-right",
+];
+
+/// A rectangle in one of the theme's sheets.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Sprite {
+    /// Index into [`Theme::sheets`].
+    pub sheet: usize,
+    pub x: i32,
+    pub y: i32,
+    pub w: u32,
+    pub h: u32,
+}
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub name: String,
+    pub tile_size: u32,
+    /// Left and top of the mine counter's first digit and the distance
+    /// between digits.
+    pub counters: (i32, i32, i32),
+    pub face_y: i32,
+    /// File of every sprite sheet, relative to the manifest.
+    pub sheets: Vec<PathBuf>,
+    pub hidden: Sprite,
+    pub question: Sprite,
+    pub pressed: Sprite,
+    /// Hidden mine shown when the game is over.
+    pub mine: Sprite,
+    /// The mine that was clicked.
+    pub exploded: Sprite,
+    pub flag: Sprite,
+    /// Flag on a tile without a mine, shown when the game is over.
+    pub wrong_flag: Sprite,
+    /// Revealed tiles by number of neighbouring mines.
+    pub tiles: [Sprite; 9],
+    pub digits: [Sprite; 10],
+    pub face: Sprite,
+    pub face_pressed: Sprite,
+    pub face_won: Sprite,
+    pub face_lost: Sprite,
+    pub border_top_left: Sprite,
+    pub border_top: Sprite,
+    /// Behind the face.
+    pub border_top_middle: Sprite,
+    pub border_top_right: Sprite,
+    pub border_left: Sprite,
+    pub border_right: Sprite,
+    pub border_bottom_left: Sprite,
+    pub border_bottom: Sprite,
+    pub border_bottom_right: Sprite,
+}
+
+impl Theme {
+    /// The built-in theme, drawn with the sheets in `assets`.
+    pub fn classic() -> Theme {
+        from_str(CLASSIC).expect("Built-in theme is invalid!")
+    }
+
+    /// Size in pixels of the screen showing a board of `w` by `h` tiles.
+    pub fn screen_size(&self, w: usize, h: usize) -> (u32, u32) {
+        (
+            self.border_left.w + w as u32 * self.tile_size + self.border_right.w,
+            self.border_top_left.h + h as u32 * self.tile_size + self.border_bottom.h,
+        )
+    }
+
+    /// Top left corner of the first tile.
+    pub fn field_origin(&self) -> (i32, i32) {
+        (self.border_left.w as i32, self.border_top_left.h as i32)
+    }
+}
+
+pub fn load(path: &Path) -> Result<Theme> {
+    from_str(&fs::read_to_string(path)?)
+}
+
+/// Manifests ending in `.theme` in `dir`, sorted by file name. A missing
+/// directory has none.
+pub fn find(dir: &Path) -> Vec<PathBuf> {
+    let mut themes: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|e| e == "theme"))
+        .collect();
+    themes.sort();
+    themes
+}
+
+pub fn from_str(text: &str) -> Result<Theme> {
+    let invalid = |message: String| Error::new(ErrorKind::InvalidData, message);
+
+    let mut lines = text.lines();
+    format::check_header(&mut lines, HEADER, 1..=VERSION, "theme")?;
+
+    let mut name = String::new();
+    let (mut tile_size, mut counters, mut face_y) = (0, (0, 0, 0), 0);
+    let mut sheet_names: Vec<&str> = vec![];
+    let mut sheets = vec![];
+    let mut sprites = HashMap::new();
+    for line in lines {
+        let error = || invalid(format!("Invalid line '{}' in theme file!", line));
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let number = |i: usize| -> Result<i32> {
+            parts.get(i).and_then(|n| n.parse().ok()).ok_or_else(error)
+        };
+        match parts.as_slice() {
+            [] => {}
+            ["name", ..] => name = parts[1..].join(" "),
+            ["tile-size", _] => tile_size = number(1)?.max(1) as u32,
+            ["counters", _, _, _] => counters = (number(1)?, number(2)?, number(3)?),
+            ["face-y", _] => face_y = number(1)?,
+            ["sheet", sheet, file] => {
+                sheet_names.push(sheet);
+                sheets.push(PathBuf::from(file));
+            }
+            [sprite, sheet, _, _, _, _] if SPRITES.contains(sprite) => {
+                let sheet = sheet_names
+                    .iter()
+                    .position(|s| s == sheet)
+                    .ok_or_else(|| invalid(format!("Unknown sheet '{}' in theme file!", sheet)))?;
+                let (w, h) = (number(4)?, number(5)?);
+                if w <= 0 || h <= 0 {
+                    return Err(error());
+                }
+                let rect = Sprite {
+                    sheet,
+                    x: number(2)?,
+                    y: number(3)?,
+                    w: w as u32,
+                    h: h as u32,
+                };
+                sprites.insert(*sprite, rect);
+            }
+            _ => return Err(error()),
+        }
+    }
+    if tile_size == 0 {
+        return Err(invalid("Missing tile size in theme file!".to_string()));
+    }
+
+    let get = |name: &str| {
+        sprites
+            .get(name)
+            .copied()
+            .ok_or_else(|| invalid(format!("Missing sprite '{}' in theme file!", name)))
+    };
+    let tile = |name: &str| {
+        let sprite = get(name)?;
+        if (sprite.w, sprite.h) != (tile_size, tile_size) {
+            return Err(invalid(format!(
+                "Sprite '{}' must be {}x{} like the tiles!",
+                name, tile_size, tile_size
+            )));
+        }
+        Ok(sprite)
+    };
+    let tiles: Vec<Sprite> = (0..9)
+        .map(|n| tile(&format!("tile-{}", n)))
+        .collect::<Result<_>>()?;
+    let digits: Vec<Sprite> = (0..10)
+        .map(|n| get(&format!("digit-{}", n)))
+        .collect::<Result<_>>()?;
+
+    Ok(Theme {
+        name,
+        tile_size,
+        counters,
+        face_y,
+        sheets,
+        hidden: tile("hidden")?,
+        question: tile("question")?,
+        pressed: tile("pressed")?,
+        mine: tile("mine")?,
+        exploded: tile("exploded")?,
+        flag: tile("flag")?,
+        wrong_flag: tile("wrong-flag")?,
+        tiles: tiles.try_into().unwrap(),
+        digits: digits.try_into().unwrap(),
+        face: get("face")?,
+        face_pressed: get("face-pressed")?,
+        face_won: get("face-won")?,
+        face_lost: get("face-lost")?,
+        border_top_left: get("border-top-left")?,
+        border_top: get("border-top")?,
+        border_top_middle: get("border-top-middle")?,
+        border_top_right: get("border-top-right")?,
+        border_left: get("border-left")?,
+        border_right: get("border-right")?,
+        border_bottom_left: get("border-bottom-left")?,
+        border_bottom: get("border-bottom")?,
+        border_bottom_right: get("border-bottom-right")?,
+    })
+}
@@ -0,0 +1,321 @@
+//! Terminal frontend, for playing without a display, e.g. over SSH.
+//!
+//! Every tile is two columns wide. The board is played with the keyboard
+//! cursor or with the mouse if the terminal reports mouse events.
+
+use std::fmt::Write as _;
+use std::io::{self, Stdout, Write};
+use std::time::Duration;
+
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent, KeyEventKind,
+    KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use crossterm::style::{Color, Stylize};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, queue};
+
+use crate::field::{Game, GameState, Tile, TileContent};
+use crate::results::{Ended, Record, Results};
+use crate::scores::MAX_NAME_LENGTH;
+
+/// Rows above the first row of tiles: the counters and the top border.
+const TOP: u16 = 2;
+/// How long to wait for input before redrawing the timer.
+const TICK: Duration = Duration::from_millis(100);
+
+pub struct Tui {
+    stdout: Stdout,
+    cursor: (usize, usize),
+    /// Lines on screen, to redraw only those that changed.
+    screen: Vec<String>,
+    results: Results,
+    record: Option<Record>,
+    /// Shown under the board until the next game.
+    message: String,
+}
+
+impl Tui {
+    /// Switches the terminal to an alternate screen in raw mode. It is
+    /// restored when the `Tui` is dropped.
+    pub fn new(game: &Game) -> io::Result<Tui> {
+        let mut stdout = io::stdout();
+        terminal::enable_raw_mode()?;
+        queue!(
+            stdout,
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            cursor::Hide
+        )?;
+        stdout.flush()?;
+        Ok(Tui {
+            stdout,
+            cursor: (game.width() / 2, game.height() / 2),
+            screen: vec![],
+            results: Results::default(),
+            record: None,
+            message: String::new(),
+        })
+    }
+
+    /// Waits briefly for input and applies it to the game. Returns true when
+    /// the player quits.
+    pub fn handle_events(&mut self, game: &mut Game) -> io::Result<bool> {
+        if !event::poll(TICK)? {
+            return Ok(false);
+        }
+        loop {
+            match event::read()? {
+                Event::Key(key)
+                    if key.kind != KeyEventKind::Release && self.handle_key(key, game) =>
+                {
+                    return Ok(true)
+                }
+                Event::Mouse(mouse) => self.handle_mouse(mouse, game),
+                Event::Resize(..) => self.screen.clear(),
+                _ => {}
+            }
+            if let Some(ended) = self.results.update(game) {
+                self.report(ended);
+            }
+            if !event::poll(Duration::ZERO)? {
+                return Ok(false);
+            }
+        }
+    }
+
+    fn report(&mut self, ended: Ended) {
+        let mut messages = vec![];
+        match ended.replay {
+            Ok(path) => messages.push(format!("Replay saved to {}", path.display())),
+            Err(err) => messages.push(format!("Cannot save replay! {}", err)),
+        }
+        if let Err(err) = ended.stats {
+            messages.push(format!("Cannot save statistics! {}", err));
+        }
+        match ended.record {
+            Ok(record) => self.record = record,
+            Err(err) => messages.push(format!("Cannot load scores! {}", err)),
+        }
+        self.message = messages.join(" ");
+    }
+
+    /// Name entry for a new best time. Enter saves it and Esc skips it.
+    fn handle_record_key(&mut self, key: KeyEvent) {
+        let record = match self.record.as_mut() {
+            Some(record) => record,
+            None => return,
+        };
+        match key.code {
+            KeyCode::Char(c) if record.name.chars().count() < MAX_NAME_LENGTH => {
+                record.name.push(c)
+            }
+            KeyCode::Backspace => {
+                record.name.pop();
+            }
+            KeyCode::Enter => {
+                if let Some(record) = self.record.take() {
+                    if let Err(err) = self.results.save_record(&record) {
+                        self.message = format!("Cannot save scores! {}", err);
+                    }
+                }
+            }
+            KeyCode::Esc => self.record = None,
+            _ => {}
+        }
+    }
+
+    fn new_game(&mut self, game: &mut Game) {
+        game.new_game();
+        self.results.new_game();
+        self.message.clear();
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, game: &mut Game) -> bool {
+        let (w, h) = game.size();
+        let (x, y) = self.cursor;
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        if ctrl && key.code == KeyCode::Char('c') {
+            return true;
+        }
+        if self.record.is_some() {
+            self.handle_record_key(key);
+            return false;
+        }
+        match key.code {
+            KeyCode::Esc | KeyCode::Char('q') => return true,
+            KeyCode::Char('z') if ctrl => {
+                game.undo();
+            }
+            KeyCode::Char('y') if ctrl => {
+                game.redo();
+            }
+            KeyCode::F(2) | KeyCode::Char('r') => self.new_game(game),
+            KeyCode::Char('m') => {
+                let enabled = !game.question_marks();
+                game.set_question_marks(enabled);
+            }
+            KeyCode::Left | KeyCode::Char('a' | 'h') => self.cursor = (x.saturating_sub(1), y),
+            KeyCode::Right | KeyCode::Char('d' | 'l') => self.cursor = ((x + 1).min(w - 1), y),
+            KeyCode::Up | KeyCode::Char('w' | 'k') => self.cursor = (x, y.saturating_sub(1)),
+            KeyCode::Down | KeyCode::Char('s' | 'j') => self.cursor = (x, (y + 1).min(h - 1)),
+            KeyCode::Char('n') => self.cursor = game.next_unrevealed(x, y, true).unwrap_or((x, y)),
+            KeyCode::Char('N') => self.cursor = game.next_unrevealed(x, y, false).unwrap_or((x, y)),
+            // Actions after the game is over are ignored.
+            KeyCode::Char(' ') | KeyCode::Enter => Tui::click(game, x, y),
+            KeyCode::Char('f') => {
+                let _ = game.flag_tile(x, y);
+            }
+            KeyCode::Char('c') => {
+                let _ = game.chord_tile(x, y);
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn handle_mouse(&mut self, mouse: MouseEvent, game: &mut Game) {
+        if self.record.is_some() {
+            return;
+        }
+        let (column, row) = (mouse.column as usize, mouse.row as usize);
+        if column < 1 || row < TOP as usize {
+            return;
+        }
+        let (x, y) = ((column - 1) / 2, row - TOP as usize);
+        if x >= game.width() || y >= game.height() {
+            return;
+        }
+        if let MouseEventKind::Down(button) = mouse.kind {
+            self.cursor = (x, y);
+            match button {
+                MouseButton::Left => Tui::click(game, x, y),
+                MouseButton::Right => {
+                    let _ = game.flag_tile(x, y);
+                }
+                MouseButton::Middle => {
+                    let _ = game.chord_tile(x, y);
+                }
+            }
+        }
+    }
+
+    /// Reveals a hidden tile or chords on a number, like a left click.
+    fn click(game: &mut Game, x: usize, y: usize) {
+        if game.get_field()[x][y].revealed {
+            let _ = game.chord_tile(x, y);
+        } else {
+            let _ = game.reveal_tile(x, y);
+        }
+    }
+
+    pub fn draw(&mut self, game: &Game) -> io::Result<()> {
+        let lines = self.render(game);
+        if lines.len() != self.screen.len() {
+            queue!(self.stdout, Clear(ClearType::All))?;
+            self.screen.clear();
+        }
+        for (i, line) in lines.iter().enumerate() {
+            if self.screen.get(i) != Some(line) {
+                queue!(self.stdout, cursor::MoveTo(0, i as u16))?;
+                write!(self.stdout, "{}", line)?;
+                queue!(self.stdout, Clear(ClearType::UntilNewLine))?;
+            }
+        }
+        self.screen = lines;
+        self.stdout.flush()
+    }
+
+    fn render(&self, game: &Game) -> Vec<String> {
+        let (w, h) = game.size();
+        let face = match game.state() {
+            GameState::Playing(_) => ":)".to_string(),
+            GameState::Victory(_) => "B)".green().to_string(),
+            GameState::Boom(_) => "X(".red().to_string(),
+        };
+        let counters = format!("{:03}", game.mines_remaining()).red();
+        let time = format!("{:03}", game.time()).red();
+        let gap = " ".repeat((w * 2).saturating_sub(10) / 2);
+        let mut lines = vec![format!(" {}{} {} {}{}", counters, gap, face, gap, time)];
+
+        let border = "-".repeat(w * 2 + 1);
+        lines.push(format!("+{}+", border));
+        let over = !matches!(game.state(), GameState::Playing(_));
+        for y in 0..h {
+            let mut line = "|".to_string();
+            for x in 0..w {
+                let symbol = Tui::symbol(&game.get_field()[x][y], over);
+                line.push(' ');
+                if (x, y) == self.cursor {
+                    let _ = write!(line, "{}", symbol.reverse());
+                } else {
+                    let _ = write!(line, "{}", symbol);
+                }
+            }
+            lines.push(line + " |");
+        }
+        lines.push(format!("+{}+", border));
+
+        let status = match game.state() {
+            GameState::Victory(t) => format!("Cleared in {:.3} s!", t.as_secs_f64()),
+            GameState::Boom(_) => "Boom!".to_string(),
+            GameState::Playing(_) => format!("Seed {}", game.seed()),
+        };
+        lines.push(status);
+        if let Some(record) = &self.record {
+            lines.push(format!(
+                "New record {:.3} s! Type your name and press Enter: {}_",
+                record.time.as_secs_f64(),
+                record.name
+            ));
+        } else if !self.message.is_empty() {
+            lines.push(self.message.clone());
+        }
+        lines.push(
+            "space reveal  f flag  c chord  n next  r new  q quit"
+                .dark_grey()
+                .to_string(),
+        );
+        lines
+    }
+
+    fn symbol(tile: &Tile, over: bool) -> crossterm::style::StyledContent<String> {
+        let (symbol, color) = match (tile.revealed, tile.flagged, &tile.content) {
+            (true, false, TileContent::Bomb) => ('*', Color::Red),
+            (true, true, TileContent::Empty(_)) => ('x', Color::Red),
+            (true, true, TileContent::Bomb) | (false, true, _) => ('F', Color::Red),
+            (true, false, TileContent::Empty(0)) => (' ', Color::Reset),
+            (true, false, TileContent::Empty(n)) => {
+                let colors = [
+                    Color::Blue,
+                    Color::Green,
+                    Color::Red,
+                    Color::DarkBlue,
+                    Color::DarkRed,
+                    Color::Cyan,
+                    Color::Magenta,
+                    Color::Grey,
+                ];
+                let digit = char::from_digit(*n as u32, 10).unwrap_or('?');
+                (digit, colors[(*n as usize - 1).min(7)])
+            }
+            (false, false, TileContent::Bomb) if over => ('*', Color::Reset),
+            (false, false, _) if tile.questioned => ('?', Color::Yellow),
+            (false, false, _) => ('.', Color::DarkGrey),
+        };
+        symbol.to_string().with(color)
+    }
+}
+
+impl Drop for Tui {
+    fn drop(&mut self) {
+        let _ = queue!(
+            self.stdout,
+            cursor::Show,
+            DisableMouseCapture,
+            LeaveAlternateScreen
+        );
+        let _ = self.stdout.flush();
+        let _ = terminal::disable_raw_mode();
+    }
+}